@@ -0,0 +1,276 @@
+//! Instruction set of Abstract Machine AM16 as a Rust type.  Instead of
+//! printing assembly text directly, the compiler produces a [Vec] of
+//! [Instr] values, which can be printed with their [Display] implementation
+//! (one instruction per line) and fed to `vm16`.  The format of each
+//! instruction is described in the comments of `vm16/vmfplusone.cpp`.
+
+use std::fmt::{Display, Formatter, Result};
+
+/// The six registers visible to software, in the order of their encoding
+/// in the instruction format (ax=0, bx=1, ... ma=5).  The internal
+/// registers pc and ir cannot be named in a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reg {
+    Ax,
+    Bx,
+    Cx,
+    Sp,
+    Bp,
+    Ma,
+}
+
+/// Source operand of an instruction: either a register or an immediate
+/// (constant).  Immediates are held as i32 so that out-of-range constants
+/// can still be represented (and rejected) before being encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Imm(i32),
+    Reg(Reg),
+}
+
+/// One AM16 instruction.  ALU instructions and mov take a src operand
+/// and a dst register, so `Sub(Operand::Reg(Ax), Bx)` is `sub ax bx`, which
+/// has semantics `bx -= ax`.  Branch instructions and call hold an
+/// absolute target address in the code segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instr {
+    Nop,
+    Add(Operand, Reg),
+    Sub(Operand, Reg),
+    Mult(Operand, Reg),
+    Div(Operand, Reg),
+    Push(Operand),
+    Pop(Reg),
+    Mov(Operand, Reg),
+    Load(Reg),
+    Store(Operand),
+    Jmp(u16),
+    Jnz(u16),
+    Jz(u16),
+    Jn(u16),
+    Call(u16),
+    Ret,
+}
+
+impl Instr {
+    /// assembly mnemonic of the instruction, such as "mult"
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instr::Nop => "nop",
+            Instr::Add(..) => "add",
+            Instr::Sub(..) => "sub",
+            Instr::Mult(..) => "mult",
+            Instr::Div(..) => "div",
+            Instr::Push(_) => "push",
+            Instr::Pop(_) => "pop",
+            Instr::Mov(..) => "mov",
+            Instr::Load(_) => "load",
+            Instr::Store(_) => "store",
+            Instr::Jmp(_) => "jmp",
+            Instr::Jnz(_) => "jnz",
+            Instr::Jz(_) => "jz",
+            Instr::Jn(_) => "jn",
+            Instr::Call(_) => "call",
+            Instr::Ret => "ret",
+        } //match
+    } //mnemonic
+} // impl Instr
+
+/// Prints a sequence of instructions one per line, in the form accepted by
+/// `vm16`.
+pub fn listing(code: &[Instr]) -> String {
+    let mut out = String::new();
+    for inst in code {
+        out.push_str(&inst.to_string());
+        out.push('\n');
+    }
+    out
+} //listing
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let name = match self {
+            Reg::Ax => "ax",
+            Reg::Bx => "bx",
+            Reg::Cx => "cx",
+            Reg::Sp => "sp",
+            Reg::Bp => "bp",
+            Reg::Ma => "ma",
+        };
+        write!(f, "{}", name)
+    }
+} // impl Display for Reg
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Operand::Imm(n) => write!(f, "{}", n),
+            Operand::Reg(r) => write!(f, "{}", r),
+        }
+    }
+} // impl Display for Operand
+
+impl Display for Instr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let m = self.mnemonic();
+        match self {
+            Instr::Nop | Instr::Ret => write!(f, "{}", m),
+            Instr::Add(s, d)
+            | Instr::Sub(s, d)
+            | Instr::Mult(s, d)
+            | Instr::Div(s, d)
+            | Instr::Mov(s, d) => write!(f, "{} {} {}", m, s, d),
+            Instr::Push(s) | Instr::Store(s) => write!(f, "{} {}", m, s),
+            Instr::Pop(d) | Instr::Load(d) => write!(f, "{} {}", m, d),
+            Instr::Jmp(t) | Instr::Jnz(t) | Instr::Jz(t) | Instr::Jn(t) | Instr::Call(t) => {
+                write!(f, "{} {}", m, t)
+            }
+        } //match
+    }
+} // impl Display for Instr
+
+/////////// binary encoding
+
+/// Errors that prevent an instruction from fitting into a 16-bit word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeError {
+    /// the immediate operand is negative or exceeds the maximum value
+    /// allowed by the instruction format (255 for ALU and mov, 2047 for
+    /// push and store).
+    ImmediateOutOfRange { instr: Instr, max: i32 },
+    /// branch or call target outside of the 4K code segment
+    TargetOutOfRange(Instr),
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            EncodeError::ImmediateOutOfRange { instr, max } => {
+                write!(f, "immediate operand of `{}` must be in range 0-{}", instr, max)
+            }
+            EncodeError::TargetOutOfRange(instr) => {
+                write!(f, "target of `{}` is outside of the code segment", instr)
+            }
+        }
+    }
+} // impl Display for EncodeError
+
+impl std::error::Error for EncodeError {}
+
+impl Reg {
+    /// all registers in order of their encoding
+    pub const ALL: [Reg; 6] = [Reg::Ax, Reg::Bx, Reg::Cx, Reg::Sp, Reg::Bp, Reg::Ma];
+
+    /// index of register in the instruction format
+    pub fn index(self) -> u16 {
+        self as u16
+    }
+
+    /// register identified by 3-bit index, None for the unused values 6 and 7
+    pub fn from_index(i: u16) -> Option<Reg> {
+        Reg::ALL.get(i as usize).copied()
+    }
+} // impl Reg
+
+impl Instr {
+    /// opcode of the instruction: its index in the table
+    /// `nop, add, sub, mult, div, push, pop, mov, load, store, jmp, jnz, jz, jn, call, ret`
+    pub fn opcode(&self) -> u16 {
+        match self {
+            Instr::Nop => 0,
+            Instr::Add(..) => 1,
+            Instr::Sub(..) => 2,
+            Instr::Mult(..) => 3,
+            Instr::Div(..) => 4,
+            Instr::Push(_) => 5,
+            Instr::Pop(_) => 6,
+            Instr::Mov(..) => 7,
+            Instr::Load(_) => 8,
+            Instr::Store(_) => 9,
+            Instr::Jmp(_) => 10,
+            Instr::Jnz(_) => 11,
+            Instr::Jz(_) => 12,
+            Instr::Jn(_) => 13,
+            Instr::Call(_) => 14,
+            Instr::Ret => 15,
+        }
+    } //opcode
+
+    /// Encodes the instruction into a 16-bit machine word, with the opcode in
+    /// the upper 4 bits.  Register source operands set bit 11 and are held
+    /// in bits 3-5; the destination register is in the lowest 3 bits.
+    pub fn encode(&self) -> std::result::Result<u16, EncodeError> {
+        let op = self.opcode() << 12;
+        let imm = |n: i32, max: i32| {
+            if n < 0 || n > max {
+                Err(EncodeError::ImmediateOutOfRange { instr: *self, max })
+            } else {
+                Ok(n as u16)
+            }
+        };
+        match self {
+            Instr::Nop | Instr::Ret => Ok(op),
+            Instr::Add(s, d)
+            | Instr::Sub(s, d)
+            | Instr::Mult(s, d)
+            | Instr::Div(s, d)
+            | Instr::Mov(s, d) => match s {
+                Operand::Imm(n) => Ok(op | (imm(*n, 255)? << 3) | d.index()),
+                Operand::Reg(r) => Ok(op | 0x0800 | (r.index() << 3) | d.index()),
+            },
+            Instr::Push(s) | Instr::Store(s) => match s {
+                Operand::Imm(n) => Ok(op | imm(*n, 2047)?),
+                Operand::Reg(r) => Ok(op | 0x0800 | (r.index() << 3)),
+            },
+            Instr::Pop(d) | Instr::Load(d) => Ok(op | d.index()),
+            Instr::Jmp(t) | Instr::Jnz(t) | Instr::Jz(t) | Instr::Jn(t) | Instr::Call(t) => {
+                if *t > 4095 {
+                    Err(EncodeError::TargetOutOfRange(*self))
+                } else {
+                    Ok(op | t)
+                }
+            }
+        } //match
+    } //encode
+
+    /// Decodes a 16-bit machine word, returning None if a register field
+    /// holds one of the unused indices 6 or 7.
+    pub fn decode(word: u16) -> Option<Instr> {
+        let dst = Reg::from_index(word & 0x0007);
+        let regsrc = word & 0x0800 != 0;
+        let src8 = || {
+            if regsrc {
+                Reg::from_index((word & 0x0038) >> 3).map(Operand::Reg)
+            } else {
+                Some(Operand::Imm(((word & 0x07f8) >> 3) as i32))
+            }
+        };
+        let src11 = || {
+            if regsrc {
+                Reg::from_index((word & 0x07f8) >> 3).map(Operand::Reg)
+            } else {
+                Some(Operand::Imm((word & 0x07ff) as i32))
+            }
+        };
+        let target = word & 0x0fff;
+        let inst = match word >> 12 {
+            0 => Instr::Nop,
+            1 => Instr::Add(src8()?, dst?),
+            2 => Instr::Sub(src8()?, dst?),
+            3 => Instr::Mult(src8()?, dst?),
+            4 => Instr::Div(src8()?, dst?),
+            5 => Instr::Push(src11()?),
+            6 => Instr::Pop(dst?),
+            7 => Instr::Mov(src8()?, dst?),
+            8 => Instr::Load(dst?),
+            9 => Instr::Store(src11()?),
+            10 => Instr::Jmp(target),
+            11 => Instr::Jnz(target),
+            12 => Instr::Jz(target),
+            13 => Instr::Jn(target),
+            14 => Instr::Call(target),
+            _ => Instr::Ret,
+        };
+        Some(inst)
+    } //decode
+} // impl Instr (encoding)
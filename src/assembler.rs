@@ -0,0 +1,171 @@
+//! AM16 assembler and disassembler, the Rust counterpart of
+//! `vm16/fponeasm.cpp`.  Assembly text such as `vm16/sample.am16` is parsed
+//! into [Instr] values, which can then be encoded into 16-bit machine words
+//! with [Instr::encode].  The [disassemble] function goes the other way.
+//!
+//! As with `vm16`, blank lines and lines starting with `#` are ignored, and
+//! a line starting with `.` ends the program.
+
+use crate::am16::{listing, EncodeError, Instr, Operand, Reg};
+use std::fmt::{Display, Formatter};
+
+/// Error found while assembling or disassembling, with the 1-based line
+/// number of the source text (or the 1-based index of the machine word).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+/// The different kinds of [AsmError]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownOpcode(String),
+    /// operand that is neither a register name nor a number
+    InvalidOperand(String),
+    /// destination that does not name a register, or `pop sp`
+    InvalidDestination(String),
+    MissingOperand,
+    ExtraOperand(String),
+    /// immediate or branch target out of range for the instruction format
+    Encode(EncodeError),
+    /// machine word that does not decode to an instruction
+    InvalidWord(u16),
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownOpcode(s) => write!(f, "unknown opcode `{}`", s),
+            AsmErrorKind::InvalidOperand(s) => write!(f, "invalid operand `{}`", s),
+            AsmErrorKind::InvalidDestination(s) => write!(f, "invalid destination `{}`", s),
+            AsmErrorKind::MissingOperand => write!(f, "missing operand"),
+            AsmErrorKind::ExtraOperand(s) => write!(f, "unexpected operand `{}`", s),
+            AsmErrorKind::Encode(e) => write!(f, "{}", e),
+            AsmErrorKind::InvalidWord(w) => write!(f, "invalid instruction word {:#06x}", w),
+        }
+    }
+} // impl Display for AsmError
+
+impl std::error::Error for AsmError {}
+
+fn register(s: &str) -> Option<Reg> {
+    Reg::ALL.iter().copied().find(|r| r.to_string() == s)
+}
+
+fn operand(s: &str) -> Result<Operand, AsmErrorKind> {
+    if let Some(r) = register(s) {
+        Ok(Operand::Reg(r))
+    } else {
+        s.parse::<i32>()
+            .map(Operand::Imm)
+            .map_err(|_| AsmErrorKind::InvalidOperand(s.to_owned()))
+    }
+} //operand
+
+fn destination(s: &str) -> Result<Reg, AsmErrorKind> {
+    register(s).ok_or_else(|| AsmErrorKind::InvalidDestination(s.to_owned()))
+}
+
+fn target(s: &str) -> Result<u16, AsmErrorKind> {
+    s.parse::<u16>()
+        .map_err(|_| AsmErrorKind::InvalidOperand(s.to_owned()))
+}
+
+/// Parses a single line of assembly, such as `div bx ax`.  Returns
+/// `Ok(None)` for blank lines and comments.  The instruction is checked
+/// to be encodable, so out-of-range immediates are reported here.
+pub fn parse_line(line: &str) -> Result<Option<Instr>, AsmErrorKind> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (op, args) = match words.split_first() {
+        None => return Ok(None),
+        Some((op, _)) if op.starts_with('#') => return Ok(None),
+        Some((op, args)) => (*op, args),
+    };
+    let arity = match op {
+        "nop" | "ret" => 0,
+        "push" | "pop" | "load" | "store" | "jmp" | "jnz" | "jz" | "jn" | "call" => 1,
+        "add" | "sub" | "mult" | "div" | "mov" => 2,
+        _ => return Err(AsmErrorKind::UnknownOpcode(op.to_owned())),
+    };
+    if args.len() < arity {
+        return Err(AsmErrorKind::MissingOperand);
+    }
+    if args.len() > arity {
+        return Err(AsmErrorKind::ExtraOperand(args[arity].to_owned()));
+    }
+    let inst = match op {
+        "nop" => Instr::Nop,
+        "ret" => Instr::Ret,
+        "add" => Instr::Add(operand(args[0])?, destination(args[1])?),
+        "sub" => Instr::Sub(operand(args[0])?, destination(args[1])?),
+        "mult" => Instr::Mult(operand(args[0])?, destination(args[1])?),
+        "div" => Instr::Div(operand(args[0])?, destination(args[1])?),
+        "mov" => Instr::Mov(operand(args[0])?, destination(args[1])?),
+        "push" => Instr::Push(operand(args[0])?),
+        "store" => Instr::Store(operand(args[0])?),
+        "pop" => match destination(args[0])? {
+            Reg::Sp => return Err(AsmErrorKind::InvalidDestination(args[0].to_owned())),
+            d => Instr::Pop(d),
+        },
+        "load" => Instr::Load(destination(args[0])?),
+        "jmp" => Instr::Jmp(target(args[0])?),
+        "jnz" => Instr::Jnz(target(args[0])?),
+        "jz" => Instr::Jz(target(args[0])?),
+        "jn" => Instr::Jn(target(args[0])?),
+        _ => Instr::Call(target(args[0])?),
+    };
+    inst.encode().map_err(AsmErrorKind::Encode)?;
+    Ok(Some(inst))
+} //parse_line
+
+/// Parses an entire `.am16` program, one instruction per line.
+pub fn parse_program(text: &str) -> Result<Vec<Instr>, AsmError> {
+    let mut code = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.starts_with('.') {
+            break;
+        }
+        match parse_line(line) {
+            Ok(Some(inst)) => code.push(inst),
+            Ok(None) => {}
+            Err(kind) => return Err(AsmError { line: i + 1, kind }),
+        }
+    }
+    Ok(code)
+} //parse_program
+
+/// Encodes a sequence of instructions into machine words.
+pub fn encode_program(code: &[Instr]) -> Result<Vec<u16>, AsmError> {
+    code.iter()
+        .enumerate()
+        .map(|(i, inst)| {
+            inst.encode().map_err(|e| AsmError {
+                line: i + 1,
+                kind: AsmErrorKind::Encode(e),
+            })
+        })
+        .collect()
+} //encode_program
+
+/// Assembles program text directly into machine words.
+pub fn assemble(text: &str) -> Result<Vec<u16>, AsmError> {
+    encode_program(&parse_program(text)?)
+}
+
+/// Disassembles machine words back into assembly text, one instruction
+/// per line.
+pub fn disassemble(words: &[u16]) -> Result<String, AsmError> {
+    let code = words
+        .iter()
+        .enumerate()
+        .map(|(i, w)| {
+            Instr::decode(*w).ok_or(AsmError {
+                line: i + 1,
+                kind: AsmErrorKind::InvalidWord(*w),
+            })
+        })
+        .collect::<Result<Vec<Instr>, AsmError>>()?;
+    Ok(listing(&code))
+} //disassemble
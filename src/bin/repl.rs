@@ -0,0 +1,27 @@
+use csc_7b_fc::repl::Session;
+use std::io::{self, BufRead, Write};
+
+fn main() {
+    // reads lines until end of input or :quit, reporting errors for the
+    // offending line only
+    let mut session = Session::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        match session.line(&line) {
+            Ok(out) if out.is_empty() => {}
+            Ok(out) => println!("{}", out),
+            Err(err) => eprintln!("ERROR: {}", err),
+        }
+        if session.finished() {
+            break;
+        }
+    }
+    println!();
+} //main
@@ -0,0 +1,740 @@
+//! Code generator from [Expr] trees to AM16 instructions, following the
+//! push/pop protocol described in the crate documentation: the code for
+//! every subexpression leaves its value on top of the stack.
+//!
+//! Variables bound by `let` are kept in the heap segment of memory.  The
+//! n-th enclosing `let` (counting from the outermost) stores its value at
+//! address `HEAP_BASE - n`, which is placed in the `ma` register before a
+//! `store` or `load` instruction.
+//!
+//! Constants are pushed directly when they fit the 11-bit immediate of
+//! `push`.  Other values of the 16-bit range, including negative ones, are
+//! synthesized in a register with `mov`, `mult`, `add` and `sub`, whose
+//! immediates are limited to 0-255.
+//!
+//! Comparisons, `&&`, `||`, `!` and `if` compile to conditional branches
+//! on the cx register.  Branch targets are absolute addresses, assuming
+//! that the program is loaded at address [ORIGIN] of the code segment.
+//!
+//! Besides this stack protocol, [compile_with] offers a second [Backend]
+//! that keeps intermediate values of arithmetic in the registers ax, bx
+//! and cx, using the Sethi-Ullman numbering to decide which operand to
+//! evaluate first.  The stack is then only used to spill values when the
+//! three registers are not enough, and for the nodes (comparisons, `if`,
+//! ...) that this backend leaves to the stack protocol.
+//!
+//! The functions of a [Program] are compiled by [compile_program] with a
+//! bp-based calling convention.  The caller pushes the arguments from
+//! left to right, executes `call` and pops the arguments after the return,
+//! when the result is in ax.  The callee saves bp and points it just above
+//! the saved value:
+//!
+//! ```text
+//!   push bp          // stack: args, return address, old bp
+//!   mov sp bp
+//!   push 0           // one slot for each level of nested let
+//!   ...              // body, its value in ax
+//!   mov bp sp        // free the slots
+//!   pop bp
+//!   ret
+//! ```
+//!
+//! With n parameters, the i-th one (from 0) is at address bp-(n+2)+i, and
+//! the k-th nested `let` of the body at bp+k, so that every call has its
+//! own variables.  The code of the functions comes first, skipped by a
+//! `jmp` to the main expression.
+
+use crate::am16::Instr::*;
+use crate::am16::Operand::{Imm, Reg as R};
+use crate::am16::Reg::*;
+use crate::am16::{Instr, Operand, Reg};
+use crate::vm16::{HEAP_BASE, STACK_LIMIT, STACK_SEGMENT};
+use crate::number::Number;
+use crate::program::Program;
+use crate::Expr;
+use std::fmt::{Display, Formatter};
+
+/// address where compiled programs are loaded, as done by `vm16`
+pub const ORIGIN: u16 = 1;
+
+/// Reasons why an expression cannot be compiled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// variable that is not bound by an enclosing `let`
+    Unbound(String),
+    /// too many nested `let` bindings to fit in the heap
+    HeapExhausted,
+    /// the compiled program does not fit in the 4K code segment
+    ProgramTooLarge(usize),
+    /// integer constant that does not fit in 16 bits
+    ConstantOutOfRange(i32),
+    /// constant that is not an integer, as written by its Display impl
+    NotAnInteger(String),
+    /// call of a function that the program does not define
+    UnknownFunction(String),
+    /// call with the wrong number of arguments: the function and how many
+    /// parameters it has
+    WrongArity(String, usize),
+    /// function whose parameters or nested lets cannot all be addressed
+    /// with the 8-bit immediates of `add` and `sub` from bp
+    FrameTooLarge(String),
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::Unbound(x) => write!(f, "unbound variable {}", x),
+            CompileError::HeapExhausted => write!(f, "too many variables for the heap"),
+            CompileError::ProgramTooLarge(n) => {
+                write!(f, "program of {} instructions is too large", n)
+            }
+            CompileError::ConstantOutOfRange(n) => {
+                write!(f, "constant {} does not fit in 16 bits", n)
+            }
+            CompileError::NotAnInteger(x) => {
+                write!(f, "constant {} is not an integer, AM16 only has integers", x)
+            }
+            CompileError::UnknownFunction(g) => write!(f, "unknown function {}", g),
+            CompileError::WrongArity(g, n) => write!(f, "function {} takes {} arguments", g, n),
+            CompileError::FrameTooLarge(g) => {
+                write!(f, "function {} has too many parameters or nested lets", g)
+            }
+        }
+    }
+} // impl Display for CompileError
+
+impl std::error::Error for CompileError {}
+
+/// Code generation strategies for [compile_with]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// every intermediate value goes through the stack
+    #[default]
+    Stack,
+    /// intermediate values are allocated to the ax, bx and cx registers
+    Registers,
+}
+
+/// Compiles an expression into a sequence of AM16 instructions by a
+/// postorder traversal of the tree.  When the program finishes, the top
+/// of the stack holds the value of the expression.
+pub fn compile(e: &Expr) -> Result<Vec<Instr>, CompileError> {
+    compile_with(e, Backend::Stack)
+}
+
+/// Compiles an expression with the given backend.  With both backends
+/// the value of the expression is left on top of the stack; the register
+/// backend also leaves it in ax.
+pub fn compile_with(e: &Expr, backend: Backend) -> Result<Vec<Instr>, CompileError> {
+    let mut gen = Compiler::default();
+    gen.main(e, backend)?;
+    gen.finish()
+} //compile_with
+
+/// Compiles the functions and the main expression of a program, see the
+/// module documentation for the calling convention.  As with
+/// [compile_with], the value of the main expression is left on top of the
+/// stack, and a program without functions compiles to the same code.
+pub fn compile_program(p: &Program, backend: Backend) -> Result<Vec<Instr>, CompileError> {
+    let mut gen = Compiler {
+        functions: p.functions.iter().map(|f| (f.name.clone(), f.params.len())).collect(),
+        ..Compiler::default()
+    };
+    let skip = (!p.functions.is_empty()).then(|| gen.branch(Jmp));
+    let mut entries = Vec::new();
+    for f in &p.functions {
+        let (n, lets) = (f.params.len(), let_depth(&f.body));
+        if n + 2 > 255 || lets > 255 {
+            return Err(CompileError::FrameTooLarge(f.name.clone()));
+        }
+        entries.push((f.name.as_str(), gen.here()));
+        gen.emit(Push(R(Bp)));
+        gen.emit(Mov(R(Sp), Bp));
+        for _ in 0..lets {
+            gen.emit(Push(Imm(0)));
+        }
+        gen.params = Some(n);
+        gen.scope = f.params.clone();
+        match backend {
+            Backend::Stack => {
+                gen.compile(&f.body)?;
+                gen.emit(Pop(Ax));
+            }
+            Backend::Registers => gen.registers(&f.body, &[Ax, Bx, Cx])?,
+        }
+        gen.emit(Mov(R(Bp), Sp));
+        gen.emit(Pop(Bp));
+        gen.emit(Ret);
+    }
+    gen.params = None;
+    gen.scope.clear();
+    if let Some(skip) = skip {
+        gen.patch(skip);
+    }
+    gen.main(&p.main, backend)?;
+    // a later definition replaces an earlier one of the same name
+    for (i, f) in std::mem::take(&mut gen.calls) {
+        if let Some((_, entry)) = entries.iter().rev().find(|(g, _)| *g == f) {
+            gen.code[i] = Call(*entry);
+        }
+    }
+    gen.finish()
+} //compile_program
+
+/// Compiles an expression over any type of numbers, whose constants must
+/// all be integers.  The program computes with 16-bit integers, so `/`
+/// truncates even if it does not for the type of the expression.
+pub fn compile_number<N: Number>(e: &Expr<N>, backend: Backend) -> Result<Vec<Instr>, CompileError> {
+    let integers = e.try_map(|n| n.to_i32().ok_or_else(|| CompileError::NotAnInteger(n.to_string())))?;
+    compile_with(&integers, backend)
+} //compile_number
+
+/// Number of instructions generated for an expression by the stack and
+/// the register backends, in this order.
+pub fn instruction_counts(e: &Expr) -> Result<(usize, usize), CompileError> {
+    let stack = compile_with(e, Backend::Stack)?.len();
+    let registers = compile_with(e, Backend::Registers)?.len();
+    Ok((stack, registers))
+} //instruction_counts
+
+// operands and operator of an arithmetic node
+fn operands(e: &Expr) -> Option<(&Expr, &Expr, char)> {
+    match e {
+        Expr::Plus(a, b) => Some((a, b, '+')),
+        Expr::Minus(a, b) => Some((a, b, '-')),
+        Expr::Times(a, b) => Some((a, b, '*')),
+        Expr::Divide(a, b) => Some((a, b, '/')),
+        Expr::Mod(a, b) => Some((a, b, '%')),
+        _ => None,
+    }
+} //operands
+
+// constant that fits the 8-bit immediate operand of the ALU instructions
+fn small(e: &Expr) -> Option<i32> {
+    match e {
+        Expr::Val(n) if (0..=255).contains(n) => Some(*n),
+        _ => None,
+    }
+}
+
+// An arithmetic node with a small constant operand is computed with an
+// immediate, as in `add 5 ax`: the other operand is returned with it.
+fn immediate<'a>(a: &'a Expr, b: &'a Expr, op: char) -> Option<(&'a Expr, i32)> {
+    match (small(a), small(b)) {
+        (_, Some(n)) => Some((a, n)),
+        (Some(n), _) if op == '+' || op == '*' => Some((b, n)),
+        _ => None,
+    }
+} //immediate
+
+// how many let expressions are nested at most in e
+fn let_depth(e: &Expr) -> usize {
+    let inner = e.children().into_iter().map(let_depth).max().unwrap_or(0);
+    match e {
+        Expr::Let(_, a, b) => let_depth(a).max(let_depth(b) + 1),
+        _ => inner,
+    }
+} //let_depth
+
+// Sethi-Ullman number: how many registers are needed to evaluate e
+// without spilling to the stack.
+fn need(e: &Expr) -> usize {
+    if let Some((a, b, op)) = operands(e) {
+        return match immediate(a, b, op) {
+            Some((x, _)) => need(x),
+            None => {
+                let (l, r) = (need(a), need(b));
+                if l == r {
+                    l + 1
+                } else {
+                    l.max(r)
+                }
+            }
+        };
+    }
+    match e {
+        Expr::Neg(a) => need(a),
+        Expr::Let(_, a, b) => need(a).max(need(b)),
+        _ => 1,
+    }
+} //need
+
+// Compiler state: the code emitted so far and the variables in scope,
+// innermost last.  Inside a function with n parameters, params is Some(n)
+// and the scope starts with the parameters.  The functions that can be
+// called are listed with their number of parameters, and the calls
+// emitted so far with their index in the code, to fill in the addresses
+// at the end.
+#[derive(Default)]
+struct Compiler {
+    code: Vec<Instr>,
+    scope: Vec<String>,
+    params: Option<usize>,
+    functions: Vec<(String, usize)>,
+    calls: Vec<(usize, String)>,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Instr) {
+        self.code.push(inst);
+    }
+
+    // Compiles the main expression, leaving its value on top of the stack.
+    fn main(&mut self, e: &Expr, backend: Backend) -> Result<(), CompileError> {
+        match backend {
+            Backend::Stack => self.compile(e),
+            Backend::Registers => {
+                self.registers(e, &[Ax, Bx, Cx])?;
+                self.emit(Push(R(Ax)));
+                Ok(())
+            }
+        }
+    } //main
+
+    // the code, if it fits in the code segment
+    fn finish(self) -> Result<Vec<Instr>, CompileError> {
+        if self.code.len() + ORIGIN as usize > STACK_SEGMENT {
+            return Err(CompileError::ProgramTooLarge(self.code.len()));
+        }
+        Ok(self.code)
+    }
+
+    // address of the next instruction to be emitted
+    fn here(&self) -> u16 {
+        self.code.len() as u16 + ORIGIN
+    }
+
+    // Emits a branch to a label that's not known yet, returning its index
+    // so that the target can be filled in by patch.
+    fn branch(&mut self, jump: fn(u16) -> Instr) -> usize {
+        self.emit(jump(0));
+        self.code.len() - 1
+    }
+
+    // sets the target of the branch at index i to the next instruction
+    fn patch(&mut self, i: usize) {
+        let target = self.here();
+        self.code[i] = match self.code[i] {
+            Jmp(_) => Jmp(target),
+            Jnz(_) => Jnz(target),
+            Jz(_) => Jz(target),
+            Jn(_) => Jn(target),
+            inst => inst,
+        };
+    } //patch
+
+    fn compile(&mut self, e: &Expr) -> Result<(), CompileError> {
+        match e {
+            Expr::Val(x) if (0..=2047).contains(x) => self.emit(Push(Imm(*x))),
+            Expr::Val(x) => {
+                self.constant(*x, Ax)?;
+                self.emit(Push(R(Ax)));
+            }
+            Expr::Neg(a) => {
+                self.compile(a)?;
+                self.emit(Push(Imm(0)));
+                self.emit(Pop(Bx)); // moves 0 into bx register
+                self.emit(Pop(Ax)); // load result for a into ax
+                self.emit(Sub(R(Ax), Bx)); // bx -= ax
+                self.emit(Push(R(Bx))); // always push result (0-a) on stack
+            }
+            Expr::Plus(a, b) => self.binop(a, b, Add(R(Bx), Ax), Ax)?,
+            Expr::Minus(a, b) => self.binop(a, b, Sub(R(Bx), Ax), Ax)?,
+            Expr::Times(a, b) => self.binop(a, b, Mult(R(Bx), Ax), Ax)?,
+            Expr::Divide(a, b) => self.binop(a, b, Div(R(Bx), Ax), Ax)?,
+            // div always leaves the remainder in cx
+            Expr::Mod(a, b) => self.binop(a, b, Div(R(Bx), Ax), Cx)?,
+            Expr::Pow(a, b) => self.power(a, b)?,
+            Expr::Var(x) => {
+                let slot = self.lookup(x)?;
+                self.address(slot);
+                self.emit(Load(Ax));
+                self.emit(Push(R(Ax)));
+            }
+            Expr::Let(x, e1, e2) => {
+                self.compile(e1)?;
+                let slot = self.scope.len();
+                if slot >= HEAP_BASE + 1 - STACK_LIMIT {
+                    return Err(CompileError::HeapExhausted);
+                }
+                self.emit(Pop(Ax));
+                self.address(slot);
+                self.emit(Store(R(Ax)));
+                self.scope.push(x.clone());
+                let body = self.compile(e2);
+                self.scope.pop();
+                body?;
+            }
+            // cx = a-b, true if negative
+            Expr::Less(a, b) => self.compare(a, b, Sub(R(Bx), Ax), Ax, Jn, 1)?,
+            // a<=b if b-a is not negative
+            Expr::LessEq(a, b) => self.compare(a, b, Sub(R(Ax), Bx), Bx, Jn, 0)?,
+            Expr::Equal(a, b) => self.compare(a, b, Sub(R(Bx), Ax), Ax, Jz, 1)?,
+            Expr::NotEqual(a, b) => self.compare(a, b, Sub(R(Bx), Ax), Ax, Jnz, 1)?,
+            Expr::And(a, b) => self.logical(a, b, Jz, 0)?,
+            Expr::Or(a, b) => self.logical(a, b, Jnz, 1)?,
+            Expr::Not(a) => {
+                self.compile(a)?;
+                self.emit(Pop(Cx));
+                self.emit(Mov(Imm(0), Bx));
+                let skip = self.branch(Jnz);
+                self.emit(Mov(Imm(1), Bx));
+                self.patch(skip);
+                self.emit(Push(R(Bx)));
+            }
+            Expr::If(c, a, b) => {
+                self.compile(c)?;
+                self.emit(Pop(Cx));
+                let to_else = self.branch(Jz);
+                self.compile(a)?;
+                let to_end = self.branch(Jmp);
+                self.patch(to_else);
+                self.compile(b)?;
+                self.patch(to_end);
+            }
+            Expr::Call(f, args) => self.call(f, args)?,
+        } //match
+        Ok(())
+    } //compile
+
+    // The arguments are pushed from left to right, and popped after the
+    // call by moving sp back.  The target of the call is filled in by
+    // compile_program once all functions have an address.
+    fn call(&mut self, f: &str, args: &[Expr]) -> Result<(), CompileError> {
+        match self.functions.iter().rev().find(|(g, _)| g == f) {
+            Some((_, n)) if *n == args.len() => {}
+            Some((_, n)) => return Err(CompileError::WrongArity(f.to_owned(), *n)),
+            None => return Err(CompileError::UnknownFunction(f.to_owned())),
+        }
+        for a in args {
+            self.compile(a)?;
+        }
+        self.calls.push((self.code.len(), f.to_owned()));
+        self.emit(Call(0));
+        if !args.is_empty() {
+            self.emit(Sub(Imm(args.len() as i32), Sp));
+        }
+        self.emit(Push(R(Ax)));
+        Ok(())
+    } //call
+
+    // The right operand is compiled first so that the left operand ends up
+    // on top of the stack: after the two pops ax holds a and bx holds b, and
+    // `op bx ax` computes `ax = a op b`.  The result is pushed from res.
+    fn binop(&mut self, a: &Expr, b: &Expr, op: Instr, res: Reg) -> Result<(), CompileError> {
+        self.compile(b)?;
+        self.compile(a)?;
+        self.emit(Pop(Ax));
+        self.emit(Pop(Bx));
+        self.emit(op);
+        self.emit(Push(R(res)));
+        Ok(())
+    } //binop
+
+    // AM16 has no power instruction: a^b is computed by multiplying ax by
+    // a, kept on the stack, |b| times, counting down in bx.  The sign of b
+    // is also kept on the stack, and a negative b gives 1/a^|b| with div.
+    fn power(&mut self, a: &Expr, b: &Expr) -> Result<(), CompileError> {
+        self.compile(b)?;
+        self.compile(a)?;
+        self.emit(Pop(Ax));
+        self.emit(Pop(Bx));
+        self.emit(Push(R(Bx))); // sign of b, for the end
+        self.emit(Mov(R(Bx), Cx));
+        let negative = self.branch(Jn);
+        let positive = self.branch(Jmp);
+        self.patch(negative);
+        self.emit(Mov(Imm(0), Cx));
+        self.emit(Sub(R(Bx), Cx));
+        self.emit(Mov(R(Cx), Bx)); // bx = -b
+        self.patch(positive);
+        self.emit(Push(R(Ax)));
+        self.emit(Mov(Imm(1), Ax));
+        let top = self.here();
+        self.emit(Mov(R(Bx), Cx));
+        let done = self.branch(Jz);
+        self.emit(Pop(Cx));
+        self.emit(Mult(R(Cx), Ax));
+        self.emit(Push(R(Cx)));
+        self.emit(Sub(Imm(1), Bx));
+        self.emit(Jmp(top));
+        self.patch(done);
+        self.emit(Pop(Cx)); // a
+        self.emit(Pop(Cx)); // b
+        let reciprocal = self.branch(Jn);
+        let end = self.branch(Jmp);
+        self.patch(reciprocal);
+        self.emit(Mov(Imm(1), Bx));
+        self.emit(Div(R(Ax), Bx));
+        self.emit(Mov(R(Bx), Ax));
+        self.patch(end);
+        self.emit(Push(R(Ax)));
+        Ok(())
+    } //power
+
+    // Computes the difference of a (in ax) and b (in bx) with the sub
+    // instruction diff, moves it from register res to cx and pushes 1 if
+    // the jump is taken and 0 otherwise when taken is 1, or the reverse
+    // when taken is 0.
+    fn compare(
+        &mut self,
+        a: &Expr,
+        b: &Expr,
+        diff: Instr,
+        res: Reg,
+        jump: fn(u16) -> Instr,
+        taken: i32,
+    ) -> Result<(), CompileError> {
+        self.compile(b)?;
+        self.compile(a)?;
+        self.emit(Pop(Ax));
+        self.emit(Pop(Bx));
+        self.emit(diff);
+        self.emit(Mov(R(res), Cx));
+        self.emit(Mov(Imm(taken), Bx));
+        let skip = self.branch(jump);
+        self.emit(Mov(Imm(1 - taken), Bx));
+        self.patch(skip);
+        self.emit(Push(R(Bx)));
+        Ok(())
+    } //compare
+
+    // Short-circuit && and ||: as soon as an operand tested with jump
+    // decides the result, jump to push it (0 for &&, 1 for ||).
+    fn logical(
+        &mut self,
+        a: &Expr,
+        b: &Expr,
+        jump: fn(u16) -> Instr,
+        decided: i32,
+    ) -> Result<(), CompileError> {
+        self.compile(a)?;
+        self.emit(Pop(Cx));
+        let first = self.branch(jump);
+        self.compile(b)?;
+        self.emit(Pop(Cx));
+        let second = self.branch(jump);
+        self.emit(Push(Imm(1 - decided)));
+        let to_end = self.branch(Jmp);
+        self.patch(first);
+        self.patch(second);
+        self.emit(Push(Imm(decided)));
+        self.patch(to_end);
+        Ok(())
+    } //logical
+
+    // Register backend: evaluates e into avail[0], using the other registers
+    // of avail as scratch.  Registers that are not in avail hold live values
+    // and are saved on the stack before anything overwrites them.
+    fn registers(&mut self, e: &Expr, avail: &[Reg]) -> Result<(), CompileError> {
+        let r0 = avail[0];
+        if let Some((a, b, op)) = operands(e) {
+            if let Some((x, n)) = immediate(a, b, op) {
+                self.registers(x, avail)?;
+                self.arith(op, Imm(n), avail);
+                return Ok(());
+            }
+            let (na, nb) = (need(a), need(b));
+            if avail.len() >= 2 && na.min(nb) < avail.len() {
+                // the operand that needs more registers goes first, and the
+                // other one is computed into avail[1] with what's left
+                if na >= nb {
+                    self.registers(a, avail)?;
+                    self.registers(b, &avail[1..])?;
+                } else {
+                    let mut order = avail.to_vec();
+                    order.swap(0, 1);
+                    self.registers(b, &order)?;
+                    order.remove(0);
+                    self.registers(a, &order)?;
+                }
+                self.arith(op, R(avail[1]), avail);
+            } else {
+                // b waits on the stack while a is computed
+                let (t, saved) = self.scratch(avail, &[r0]);
+                self.registers(b, avail)?;
+                self.emit(Push(R(r0)));
+                self.registers(a, avail)?;
+                self.emit(Pop(t));
+                self.arith(op, R(t), avail);
+                self.release(t, saved);
+            }
+            return Ok(());
+        }
+        match e {
+            Expr::Val(n) => self.constant(*n, r0)?,
+            Expr::Neg(a) => {
+                self.registers(a, avail)?;
+                let (t, saved) = self.scratch(avail, &[r0]);
+                self.emit(Mov(Imm(0), t));
+                self.emit(Sub(R(r0), t));
+                self.emit(Mov(R(t), r0));
+                self.release(t, saved);
+            }
+            Expr::Var(x) => {
+                let slot = self.lookup(x)?;
+                self.address(slot);
+                self.emit(Load(r0));
+            }
+            Expr::Let(x, e1, e2) => {
+                self.registers(e1, avail)?;
+                let slot = self.scope.len();
+                if slot >= HEAP_BASE + 1 - STACK_LIMIT {
+                    return Err(CompileError::HeapExhausted);
+                }
+                self.address(slot);
+                self.emit(Store(R(r0)));
+                self.scope.push(x.clone());
+                let body = self.registers(e2, avail);
+                self.scope.pop();
+                body?;
+            }
+            // everything else is compiled with the stack protocol, which
+            // uses all of ax, bx and cx
+            _ => {
+                let live: Vec<Reg> = [Ax, Bx, Cx]
+                    .into_iter()
+                    .filter(|r| !avail.contains(r))
+                    .collect();
+                for r in &live {
+                    self.emit(Push(R(*r)));
+                }
+                self.compile(e)?;
+                self.emit(Pop(r0));
+                for r in live.iter().rev() {
+                    self.emit(Pop(*r));
+                }
+            }
+        } //match
+        Ok(())
+    } //registers
+
+    // Emits `avail[0] = avail[0] op src`.  Since div always writes the
+    // remainder into cx, cx is saved around it when it holds a live value,
+    // and a quotient meant for cx is computed in another register.
+    fn arith(&mut self, op: char, src: Operand, avail: &[Reg]) {
+        let r0 = avail[0];
+        match op {
+            '+' => self.emit(Add(src, r0)),
+            '-' => self.emit(Sub(src, r0)),
+            '*' => self.emit(Mult(src, r0)),
+            '/' if r0 == Cx => {
+                let exclude = match src {
+                    R(r) => vec![Cx, r],
+                    _ => vec![Cx],
+                };
+                let (t, saved) = self.scratch(avail, &exclude);
+                self.emit(Mov(R(Cx), t));
+                self.emit(Div(src, t));
+                self.emit(Mov(R(t), Cx));
+                self.release(t, saved);
+            }
+            // the remainder is written after the quotient
+            '%' if r0 == Cx => self.emit(Div(src, Cx)),
+            _ => {
+                let save = !avail.contains(&Cx);
+                if save {
+                    self.emit(Push(R(Cx)));
+                }
+                self.emit(Div(src, r0));
+                if op == '%' {
+                    self.emit(Mov(R(Cx), r0));
+                }
+                if save {
+                    self.emit(Pop(Cx));
+                }
+            }
+        } //match
+    } //arith
+
+    // A scratch register of avail that is not excluded, or else one of ax,
+    // bx and cx whose value is pushed to be restored by release.
+    fn scratch(&mut self, avail: &[Reg], exclude: &[Reg]) -> (Reg, bool) {
+        if let Some(r) = avail.iter().find(|r| !exclude.contains(r)) {
+            return (*r, false);
+        }
+        let r = [Ax, Bx, Cx]
+            .into_iter()
+            .find(|r| !exclude.contains(r))
+            .expect("at most two registers are excluded");
+        self.emit(Push(R(r)));
+        (r, true)
+    } //scratch
+
+    fn release(&mut self, r: Reg, saved: bool) {
+        if saved {
+            self.emit(Pop(r));
+        }
+    }
+
+    // slot of the innermost binding of x: its position in the scope
+    fn lookup(&self, x: &str) -> Result<usize, CompileError> {
+        self.scope
+            .iter()
+            .rposition(|y| y == x)
+            .ok_or_else(|| CompileError::Unbound(x.to_owned()))
+    }
+
+    // Places the address of a slot in ma: HEAP_BASE - slot in the main
+    // expression, or an address relative to bp inside a function.
+    fn address(&mut self, slot: usize) {
+        match self.params {
+            None => {
+                let a = (HEAP_BASE - slot) as i32;
+                self.constant(a, Ma).expect("heap addresses fit in 16 bits");
+            }
+            Some(n) => {
+                self.emit(Mov(R(Bp), Ma));
+                if slot < n {
+                    self.emit(Sub(Imm((n + 2 - slot) as i32), Ma));
+                } else if slot > n {
+                    self.emit(Add(Imm((slot - n) as i32), Ma));
+                }
+            }
+        }
+    } //address
+
+    // Loads the constant n into register r.  Immediates can be at most 255,
+    // so larger values are built in base 128 as `h*128+l` with h <= 255
+    // (32767 is 255*128+127), and negative values are subtracted from 0 as
+    // `0-h*128-l`, where l may be up to 255 to reach -32768.
+    fn constant(&mut self, n: i32, r: Reg) -> Result<(), CompileError> {
+        if n < i16::MIN as i32 || n > i16::MAX as i32 {
+            return Err(CompileError::ConstantOutOfRange(n));
+        }
+        let m = n.abs();
+        if n >= 0 && m <= 255 {
+            self.emit(Mov(Imm(m), r));
+            return Ok(());
+        }
+        let op = if n < 0 { Sub } else { Add };
+        let h = (m / 128).min(255);
+        let l = m - h * 128;
+        if n < 0 {
+            self.emit(Mov(Imm(0), r));
+            if h > 0 {
+                self.emit(Sub(Imm(h), r));
+            }
+        } else {
+            self.emit(Mov(Imm(h), r));
+        }
+        if h > 0 {
+            self.emit(Mult(Imm(128), r));
+        }
+        if l > 0 {
+            self.emit(op(Imm(l), r));
+        }
+        Ok(())
+    } //constant
+} // impl Compiler
+
+// Number of instructions that the stack backend needs to push the
+// constant n, or None if n does not fit in 16 bits.
+pub(crate) fn constant_size(n: i32) -> Option<usize> {
+    if (0..=2047).contains(&n) {
+        return Some(1);
+    }
+    let mut gen = Compiler::default();
+    gen.constant(n, Ax).ok()?;
+    Some(gen.code.len() + 1)
+} //constant_size
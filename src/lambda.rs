@@ -0,0 +1,750 @@
+//! Untyped lambda calculus, the Rust counterpart of
+//! `fsharp/lambdacalc.fsx`.  Terms are written with `\` (or `λ`) for
+//! lambda, as in `\x y.x` for `fun x y -> x`; application is by
+//! juxtaposition and associates to the left, and the body of a lambda
+//! extends as far to the right as possible.  `let x = a in b` means
+//! `(\x.b) a`, but the [crate::types] inference treats it differently.
+//!
+//! Terms are reduced one beta step at a time with [step], or to a normal
+//! form with [normalize], by one of two [Strategy]s.  Substitution renames
+//! bound variables when needed, so that free variables are never
+//! captured.  Since renaming changes the names of bound variables, terms
+//! are compared up to those names with [alpha_eq], which converts them to
+//! the nameless [DeBruijn] form, and by their normal forms with
+//! [beta_eq].  The [Prelude] defines the combinators of the F# script,
+//! which a term can then use by name:
+//!
+//! ```
+//! use csc_7b_fc::lambda::{church, normalize, Prelude, Strategy};
+//! let prelude = Prelude::standard();
+//! let five = prelude.parse("ADD church2 church3").unwrap();
+//! let nf = normalize(&five, Strategy::NormalOrder, 1000).unwrap();
+//! assert_eq!(nf, church(5));
+//! ```
+//!
+//! The calculator's arithmetic expressions are translated to such terms by
+//! [encode], and Church numerals are turned back into numbers by [decode].
+
+use crate::Expr;
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+/// number of beta reductions that [Prelude::eval] performs at most
+pub const DEFAULT_MAX_STEPS: usize = 100_000;
+
+/// Largest value that [encode] accepts, for the literals and for every
+/// subexpression.  Terms are reduced recursively, and a Church numeral n
+/// is n applications deep: much larger ones overflow the stack.
+pub const MAX_NUMERAL: usize = 1000;
+
+/// Abstract syntax of lambda terms
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Term {
+    Var(String),
+    Lam(String, Box<Term>), // \x.body
+    App(Box<Term>, Box<Term>),
+    Let(String, Box<Term>, Box<Term>), // let x = a in b
+}
+use Term::*;
+
+/// Errors of parsing and reducing lambda terms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LambdaError {
+    /// the character offset of the error, with a description of what was
+    /// expected there
+    Syntax { offset: usize, expected: &'static str },
+    /// no normal form was reached in the given number of steps
+    StepLimit(usize),
+    /// an expression that [encode] cannot translate, printed
+    NotEncodable(String),
+}
+
+impl Display for LambdaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LambdaError::Syntax { offset, expected } => {
+                write!(f, "parse error at offset {}: expected {}", offset, expected)
+            }
+            LambdaError::StepLimit(n) => write!(f, "no normal form after {} steps", n),
+            LambdaError::NotEncodable(e) => write!(f, "cannot encode {} with Church numerals", e),
+        }
+    }
+} // impl Display for LambdaError
+
+impl std::error::Error for LambdaError {}
+
+/// How a term is reduced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// The leftmost outermost redex first, also inside lambdas.  This finds
+    /// the normal form whenever there is one.
+    #[default]
+    NormalOrder,
+    /// The function, then the argument, are reduced to values (lambdas or
+    /// variables) before the call.  Lambda bodies are not reduced, so the
+    /// result may not be a normal form, and an argument without a normal
+    /// form loops even if it's not needed, as `1/0` does in F#.
+    CallByValue,
+}
+
+impl Term {
+    /// a variable
+    pub fn var(x: &str) -> Term {
+        Var(x.to_owned())
+    }
+
+    /// the lambda `\x.body`
+    pub fn lam(x: &str, body: Term) -> Term {
+        Lam(x.to_owned(), Box::new(body))
+    }
+
+    /// the application `f a`
+    pub fn app(f: Term, a: Term) -> Term {
+        App(Box::new(f), Box::new(a))
+    }
+
+    /// variables that occur free in the term
+    pub fn free_vars(&self) -> BTreeSet<String> {
+        let mut free = BTreeSet::new();
+        self.collect_free(&mut Vec::new(), &mut free);
+        free
+    }
+
+    // adds the variables of the term that are not bound here or in bound
+    fn collect_free(&self, bound: &mut Vec<String>, free: &mut BTreeSet<String>) {
+        match self {
+            Var(x) if !bound.contains(x) => {
+                free.insert(x.clone());
+            }
+            Var(_) => {}
+            Lam(x, body) => {
+                bound.push(x.clone());
+                body.collect_free(bound, free);
+                bound.pop();
+            }
+            App(f, a) => {
+                f.collect_free(bound, free);
+                a.collect_free(bound, free);
+            }
+            Let(x, a, body) => {
+                a.collect_free(bound, free);
+                bound.push(x.clone());
+                body.collect_free(bound, free);
+                bound.pop();
+            }
+        }
+    } //collect_free
+
+    /// Replaces the free occurrences of x by s.  A lambda (or let) whose
+    /// variable is free in s is renamed first, so that s keeps its meaning.
+    pub fn subst(&self, x: &str, s: &Term) -> Term {
+        match self {
+            Var(y) if y == x => s.clone(),
+            Var(_) => self.clone(),
+            App(f, a) => Term::app(f.subst(x, s), a.subst(x, s)),
+            Lam(y, body) => {
+                let (y, body) = subst_under(y, body, x, s);
+                Lam(y, Box::new(body))
+            }
+            Let(y, a, body) => {
+                let (y, body) = subst_under(y, body, x, s);
+                Let(y, Box::new(a.subst(x, s)), Box::new(body))
+            }
+        }
+    } //subst
+
+    /// true for a lambda or a variable, the terms that call-by-value
+    /// passes to functions
+    pub fn is_value(&self) -> bool {
+        matches!(self, Var(_) | Lam(..))
+    }
+
+    // Prints the term in a context: 0 where a lambda can extend to the
+    // right, 1 for the function of an application, where a lambda needs
+    // parentheses, and 2 for an argument, where an application also does.
+    fn fmt_ctx(&self, ctx: u8, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Var(x) => write!(f, "{}", x),
+            Lam(..) => {
+                if ctx > 0 {
+                    write!(f, "(")?;
+                }
+                write!(f, "\\")?;
+                let mut t = self;
+                let mut first = true;
+                while let Lam(x, body) = t {
+                    write!(f, "{}{}", if first { "" } else { " " }, x)?;
+                    first = false;
+                    t = body;
+                }
+                write!(f, ".")?;
+                t.fmt_ctx(0, f)?;
+                if ctx > 0 {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Let(x, a, body) => {
+                if ctx > 0 {
+                    write!(f, "(")?;
+                }
+                write!(f, "let {} = ", x)?;
+                a.fmt_ctx(0, f)?;
+                write!(f, " in ")?;
+                body.fmt_ctx(0, f)?;
+                if ctx > 0 {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            App(g, a) => {
+                if ctx == 2 {
+                    write!(f, "(")?;
+                }
+                g.fmt_ctx(1, f)?;
+                write!(f, " ")?;
+                a.fmt_ctx(2, f)?;
+                if ctx == 2 {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    } //fmt_ctx
+} // impl Term
+
+/// Prints a term in the syntax accepted by [parse_term], with consecutive
+/// lambdas merged as in `\x y.x`.
+impl Display for Term {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.fmt_ctx(0, f)
+    }
+}
+
+// Substitution of s for x in the body of a binder of y: the binder and
+// the new body.  Nothing changes if y is x, since x is not free inside.
+fn subst_under(y: &str, body: &Term, x: &str, s: &Term) -> (String, Term) {
+    if y == x {
+        return (y.to_owned(), body.clone());
+    }
+    let free = s.free_vars();
+    if !free.contains(y) {
+        return (y.to_owned(), body.subst(x, s));
+    }
+    let mut avoid = free;
+    avoid.extend(body.free_vars());
+    avoid.insert(x.to_owned());
+    let z = fresh(y, &avoid);
+    let renamed = body.subst(y, &Var(z.clone()));
+    let body = renamed.subst(x, s);
+    (z, body)
+} //subst_under
+
+// x followed by the first number that makes it different from the names
+// to avoid
+fn fresh(x: &str, avoid: &BTreeSet<String>) -> String {
+    let stem = x.trim_end_matches(|c: char| c.is_ascii_digit());
+    (1..)
+        .map(|i| format!("{}{}", stem, i))
+        .find(|y| !avoid.contains(y))
+        .unwrap_or_default()
+} //fresh
+
+/// The term after one reduction step, or None if the strategy finds no
+/// redex to reduce.
+pub fn step(t: &Term, strategy: Strategy) -> Option<Term> {
+    match (t, strategy) {
+        (Var(_), _) => None,
+        (Lam(x, body), Strategy::NormalOrder) => {
+            step(body, strategy).map(|b| Lam(x.clone(), Box::new(b)))
+        }
+        (Lam(..), Strategy::CallByValue) => None,
+        (Let(x, a, body), Strategy::NormalOrder) => Some(body.subst(x, a)),
+        (Let(x, a, body), Strategy::CallByValue) => match step(a, strategy) {
+            Some(b) => Some(Let(x.clone(), Box::new(b), body.clone())),
+            None if a.is_value() => Some(body.subst(x, a)),
+            None => None,
+        },
+        (App(f, a), Strategy::NormalOrder) => match &**f {
+            Lam(x, body) => Some(body.subst(x, a)),
+            _ => match step(f, strategy) {
+                Some(g) => Some(App(Box::new(g), a.clone())),
+                None => step(a, strategy).map(|b| App(f.clone(), Box::new(b))),
+            },
+        },
+        (App(f, a), Strategy::CallByValue) => {
+            if let Some(g) = step(f, strategy) {
+                return Some(App(Box::new(g), a.clone()));
+            }
+            if let Some(b) = step(a, strategy) {
+                return Some(App(f.clone(), Box::new(b)));
+            }
+            match &**f {
+                Lam(x, body) if a.is_value() => Some(body.subst(x, a)),
+                _ => None,
+            }
+        }
+    } //match
+} //step
+
+/// Reduces a term until the strategy finds no more redexes, or fails with
+/// [LambdaError::StepLimit] after max_steps reductions.
+pub fn normalize(t: &Term, strategy: Strategy, max_steps: usize) -> Result<Term, LambdaError> {
+    let mut t = t.clone();
+    for _ in 0..max_steps {
+        match step(&t, strategy) {
+            Some(next) => t = next,
+            None => return Ok(t),
+        }
+    }
+    match step(&t, strategy) {
+        None => Ok(t),
+        Some(_) => Err(LambdaError::StepLimit(max_steps)),
+    }
+} //normalize
+
+/// Lambda terms with de Bruijn indices: a bound variable is the number of
+/// lambdas between it and its own, so `\x y.x` is `\.\.1`.  Free
+/// variables keep their names.  Terms that only differ in the names of
+/// bound variables have the same de Bruijn form.  There is no `let`:
+/// `let x = a in b` is converted as `(\x.b) a`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeBruijn {
+    Bound(usize),
+    Free(String),
+    Lam(Box<DeBruijn>),
+    App(Box<DeBruijn>, Box<DeBruijn>),
+}
+
+impl Term {
+    /// the term with de Bruijn indices in place of bound variables
+    pub fn to_de_bruijn(&self) -> DeBruijn {
+        self.indexed(&mut Vec::new())
+    }
+
+    // conversion with the variables of the enclosing lambdas, innermost last
+    fn indexed(&self, bound: &mut Vec<String>) -> DeBruijn {
+        match self {
+            Var(x) => match bound.iter().rev().position(|y| y == x) {
+                Some(i) => DeBruijn::Bound(i),
+                None => DeBruijn::Free(x.clone()),
+            },
+            Lam(x, body) => {
+                bound.push(x.clone());
+                let b = body.indexed(bound);
+                bound.pop();
+                DeBruijn::Lam(Box::new(b))
+            }
+            App(f, a) => DeBruijn::App(Box::new(f.indexed(bound)), Box::new(a.indexed(bound))),
+            Let(x, a, body) => {
+                let f = Lam(x.clone(), body.clone()).indexed(bound);
+                DeBruijn::App(Box::new(f), Box::new(a.indexed(bound)))
+            }
+        }
+    } //indexed
+} // impl Term
+
+impl DeBruijn {
+    /// Names the bound variables back: the lambda at depth d (from 0 at
+    /// the outermost) binds `x0`, `x1`, ..., with underscores added to the
+    /// `x` if a free variable has a name of that form.  An index that
+    /// points past the outermost lambda becomes the free variable `_k`, k
+    /// being the number of missing lambdas - 1.
+    pub fn to_term(&self) -> Term {
+        let mut free = BTreeSet::new();
+        self.collect_free(&mut free);
+        let mut prefix = String::from("x");
+        let numbered = |y: &String, prefix: &str| {
+            y.strip_prefix(prefix)
+                .is_some_and(|d| !d.is_empty() && d.chars().all(|c| c.is_ascii_digit()))
+        };
+        while free.iter().any(|y| numbered(y, &prefix)) {
+            prefix.push('_');
+        }
+        self.named(&mut Vec::new(), &prefix)
+    } //to_term
+
+    fn collect_free(&self, free: &mut BTreeSet<String>) {
+        match self {
+            DeBruijn::Free(x) => {
+                free.insert(x.clone());
+            }
+            DeBruijn::Bound(_) => {}
+            DeBruijn::Lam(body) => body.collect_free(free),
+            DeBruijn::App(f, a) => {
+                f.collect_free(free);
+                a.collect_free(free);
+            }
+        }
+    } //collect_free
+
+    // conversion with the names of the enclosing lambdas, innermost last
+    fn named(&self, names: &mut Vec<String>, prefix: &str) -> Term {
+        match self {
+            DeBruijn::Bound(i) => match names.len().checked_sub(i + 1) {
+                Some(k) => Var(names[k].clone()),
+                None => Var(format!("_{}", i - names.len())),
+            },
+            DeBruijn::Free(x) => Var(x.clone()),
+            DeBruijn::Lam(body) => {
+                names.push(format!("{}{}", prefix, names.len()));
+                let b = body.named(names, prefix);
+                Lam(names.pop().unwrap_or_default(), Box::new(b))
+            }
+            DeBruijn::App(f, a) => Term::app(f.named(names, prefix), a.named(names, prefix)),
+        }
+    } //named
+
+    // as Term::fmt_ctx
+    fn fmt_ctx(&self, ctx: u8, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeBruijn::Bound(i) => write!(f, "{}", i),
+            DeBruijn::Free(x) => write!(f, "{}", x),
+            DeBruijn::Lam(body) => {
+                if ctx > 0 {
+                    write!(f, "(")?;
+                }
+                write!(f, "\\.")?;
+                body.fmt_ctx(0, f)?;
+                if ctx > 0 {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            DeBruijn::App(g, a) => {
+                if ctx == 2 {
+                    write!(f, "(")?;
+                }
+                g.fmt_ctx(1, f)?;
+                write!(f, " ")?;
+                a.fmt_ctx(2, f)?;
+                if ctx == 2 {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    } //fmt_ctx
+} // impl DeBruijn
+
+/// Prints indices as numbers and lambdas as `\.`, as in `\.\.1 0`.
+impl Display for DeBruijn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.fmt_ctx(0, f)
+    }
+}
+
+/// true if the terms only differ in the names of bound variables
+pub fn alpha_eq(a: &Term, b: &Term) -> bool {
+    a.to_de_bruijn() == b.to_de_bruijn()
+}
+
+/// True if the terms have alpha-equivalent normal forms, which are found
+/// in normal order with at most max_steps steps for each term.
+pub fn beta_eq(a: &Term, b: &Term, max_steps: usize) -> Result<bool, LambdaError> {
+    let a = normalize(a, Strategy::NormalOrder, max_steps)?;
+    let b = normalize(b, Strategy::NormalOrder, max_steps)?;
+    Ok(alpha_eq(&a, &b))
+} //beta_eq
+
+/// the Church numeral `\f x.f (f ... (f x))` with n applications of f
+pub fn church(n: usize) -> Term {
+    let mut body = Term::var("x");
+    for _ in 0..n {
+        body = Term::app(Term::var("f"), body);
+    }
+    Term::lam("f", Term::lam("x", body))
+} //church
+
+/// Translates an arithmetic expression to a term that normalizes to the
+/// Church numeral of its value, using ADD, TIMES and SUBTRACT of the
+/// [PRELUDE].  Only non-negative literals, `+`, `*` and `-` can be
+/// translated, and the values of the subexpressions must not exceed
+/// [MAX_NUMERAL].  Church numerals are natural numbers, so subtraction is
+/// truncated at zero: `2-3` gives ZERO where [crate::eval] gives -1.
+pub fn encode(e: &Expr) -> Result<Term, LambdaError> {
+    let (t, _) = encode_names(e)?;
+    Ok(Prelude::standard().expand(&t))
+} //encode
+
+// The encoding with the names of the prelude combinators left free, and
+// the value that it normalizes to.
+fn encode_names(e: &Expr) -> Result<(Term, usize), LambdaError> {
+    let (name, a, b) = match e {
+        Expr::Val(n) if (0..=MAX_NUMERAL as i32).contains(n) => {
+            return Ok((church(*n as usize), *n as usize))
+        }
+        Expr::Plus(a, b) => ("ADD", a, b),
+        Expr::Times(a, b) => ("TIMES", a, b),
+        Expr::Minus(a, b) => ("SUBTRACT", a, b),
+        _ => return Err(LambdaError::NotEncodable(e.to_string())),
+    };
+    let ((ta, va), (tb, vb)) = (encode_names(a)?, encode_names(b)?);
+    let v = match e {
+        Expr::Plus(..) => va + vb,
+        Expr::Times(..) => va * vb,
+        _ => va.saturating_sub(vb),
+    };
+    if v > MAX_NUMERAL {
+        return Err(LambdaError::NotEncodable(e.to_string()));
+    }
+    Ok((Term::app(Term::app(Term::var(name), ta), tb), v))
+} //encode_names
+
+/// The number n of a Church numeral `\f x.f (f ... (f x))`, whatever the
+/// names of f and x, or None if the term is not one.  Normalize the
+/// result of [encode] first.
+pub fn decode(t: &Term) -> Option<usize> {
+    let (f, x, mut body) = match t {
+        Lam(f, b) => match &**b {
+            Lam(x, body) if x != f => (f, x, &**body),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let mut n = 0;
+    loop {
+        match body {
+            Var(y) if y == x => return Some(n),
+            App(g, a) if matches!(&**g, Var(y) if y == f) => {
+                n += 1;
+                body = a;
+            }
+            _ => return None,
+        }
+    }
+} //decode
+
+/// Parses a term such as `\x y.x (y x)` or `let i = \x.x in i i`.
+/// Identifiers are made of letters, digits, `_` and `'`, and `let` and `in`
+/// are keywords.
+pub fn parse_term(inp: &str) -> Result<Term, LambdaError> {
+    let chars: Vec<(usize, char)> = inp.char_indices().collect();
+    let mut p = Parser { chars, pos: 0, len: inp.len() };
+    let t = p.term()?;
+    match p.peek() {
+        None => Ok(t),
+        Some(_) => Err(p.error("end of input")),
+    }
+} //parse_term
+
+// Recursive descent parser over the characters of the input: a term is
+// a lambda, or a sequence of atoms (variables and parenthesized terms)
+// applied to each other, whose last argument may also be a lambda.
+struct Parser {
+    chars: Vec<(usize, char)>,
+    pos: usize,
+    len: usize,
+}
+
+// words that cannot be variables
+const KEYWORDS: [&str; 2] = ["let", "in"];
+
+// λ is a letter to Unicode but starts an abstraction here
+fn ident_char(c: char) -> bool {
+    (c.is_alphanumeric() && c != 'λ') || c == '_' || c == '\''
+}
+
+impl Parser {
+    // the next character that is not white space
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.get(self.pos).is_some_and(|(_, c)| c.is_whitespace()) {
+            self.pos += 1;
+        }
+        self.chars.get(self.pos).map(|(_, c)| *c)
+    }
+
+    fn error(&self, expected: &'static str) -> LambdaError {
+        let offset = self.chars.get(self.pos).map_or(self.len, |(i, _)| *i);
+        LambdaError::Syntax { offset, expected }
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        self.peek().filter(|c| ident_char(*c))?;
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|(_, c)| ident_char(*c)) {
+            self.pos += 1;
+        }
+        Some(self.chars[start..self.pos].iter().map(|(_, c)| c).collect())
+    } //ident
+
+    // the identifier that comes next, without consuming it
+    fn next_ident(&mut self) -> Option<String> {
+        let start = self.pos;
+        let x = self.ident();
+        self.pos = start;
+        x
+    }
+
+    // consumes the keyword k if it's next
+    fn keyword(&mut self, k: &str) -> bool {
+        let found = self.next_ident().is_some_and(|x| x == k);
+        if found {
+            self.ident();
+        }
+        found
+    }
+
+    fn term(&mut self) -> Result<Term, LambdaError> {
+        if self.keyword("let") {
+            let x = match self.next_ident() {
+                Some(x) if !KEYWORDS.contains(&x.as_str()) => x,
+                _ => return Err(self.error("a variable")),
+            };
+            self.ident();
+            if self.peek() != Some('=') {
+                return Err(self.error("'='"));
+            }
+            self.pos += 1;
+            let a = self.term()?;
+            if !self.keyword("in") {
+                return Err(self.error("'in'"));
+            }
+            let body = self.term()?;
+            return Ok(Let(x, Box::new(a), Box::new(body)));
+        }
+        if let Some('\\' | 'λ') = self.peek() {
+            self.pos += 1;
+            let mut params = Vec::new();
+            while let Some(x) = self.ident() {
+                params.push(x);
+            }
+            if params.is_empty() {
+                return Err(self.error("a variable"));
+            }
+            if self.peek() != Some('.') {
+                return Err(self.error("'.'"));
+            }
+            self.pos += 1;
+            let body = self.term()?;
+            return Ok(params.iter().rev().fold(body, |b, x| Term::lam(x, b)));
+        }
+        let mut t = self.atom()?;
+        loop {
+            let next = self.next_ident();
+            match self.peek() {
+                _ if next.as_deref() == Some("in") => return Ok(t),
+                _ if next.as_deref() == Some("let") => return Ok(Term::app(t, self.term()?)),
+                Some('\\' | 'λ') => return Ok(Term::app(t, self.term()?)),
+                Some(c) if c == '(' || ident_char(c) => t = Term::app(t, self.atom()?),
+                _ => return Ok(t),
+            }
+        }
+    } //term
+
+    fn atom(&mut self) -> Result<Term, LambdaError> {
+        if self.peek() == Some('(') {
+            self.pos += 1;
+            let t = self.term()?;
+            if self.peek() != Some(')') {
+                return Err(self.error("')'"));
+            }
+            self.pos += 1;
+            return Ok(t);
+        }
+        match self.next_ident() {
+            Some(x) if !KEYWORDS.contains(&x.as_str()) => {
+                self.ident();
+                Ok(Var(x))
+            }
+            _ => Err(self.error("a variable, '(' or a lambda")),
+        }
+    } //atom
+} // impl Parser
+
+/// The combinators of `fsharp/lambdacalc.fsx`, in the order of the script.
+/// Each may use the ones before it.  Without types, some differ from the
+/// F# definitions: IFELSE needs no thunks since normal order only reduces
+/// the branch that is taken, and FIX is the Y combinator, which F# cannot
+/// type.
+pub const PRELUDE: &[(&str, &str)] = &[
+    ("I", "\\x.x"),
+    ("K", "\\x y.x"),
+    ("S", "\\x y z.x z (y z)"),
+    ("TRUE", "K"),
+    ("ZERO", "\\x.K I x"),
+    ("FALSE", "ZERO"),
+    ("IFELSE0", "\\a b c.a b c"),
+    ("IFELSE", "\\a b c.a b c"),
+    ("AND", "\\x y.x y FALSE"),
+    ("OR", "\\x y.x TRUE y"),
+    ("NOT", "\\x.x FALSE TRUE"),
+    ("CONS", "\\x y.\\s.s x y"),
+    ("CAR", "\\p.p TRUE"),
+    ("CDR", "\\p.p FALSE"),
+    ("NIL", "ZERO"),
+    ("ISNIL", "\\p.p (\\x y z.FALSE) TRUE"),
+    ("church2", "\\f x.f (f x)"),
+    ("church3", "\\f x.f (f (f x))"),
+    ("ONE", "\\f x.f x"),
+    ("SUCC", "\\n f x.n f (f x)"),
+    ("ADD", "\\m n f x.m f (n f x)"),
+    ("TIMES", "\\m n f x.m (n f) x"),
+    ("EXPT", "\\m n.n m"),
+    ("ISZERO", "\\n.n (\\x.FALSE) TRUE"),
+    ("BASE", "\\x.CONS ZERO ZERO x"),
+    ("NEXT", "\\p.CONS (CDR p) (SUCC (CDR p))"),
+    ("PRED", "\\n.CAR (n NEXT BASE)"),
+    ("SUBTRACT", "\\m n.n PRED m"),
+    ("FIX", "\\f.(\\x.f (x x)) (\\x.f (x x))"),
+];
+
+/// Named terms that other terms can refer to.  The definitions are closed:
+/// the names they use are replaced by the earlier definitions.
+#[derive(Debug, Clone, Default)]
+pub struct Prelude {
+    defs: Vec<(String, Term)>,
+}
+
+impl Prelude {
+    /// a prelude without definitions
+    pub fn new() -> Self {
+        Prelude::default()
+    }
+
+    /// a prelude with the definitions of [PRELUDE]
+    pub fn standard() -> Self {
+        let mut prelude = Prelude::new();
+        for (name, src) in PRELUDE {
+            prelude.define(name, src).expect("the prelude parses");
+        }
+        prelude
+    } //standard
+
+    /// Parses src and defines name as it, replacing an earlier definition.
+    pub fn define(&mut self, name: &str, src: &str) -> Result<(), LambdaError> {
+        let t = self.parse(src)?;
+        self.defs.retain(|(x, _)| x != name);
+        self.defs.push((name.to_owned(), t));
+        Ok(())
+    } //define
+
+    /// the closed term that name stands for
+    pub fn get(&self, name: &str) -> Option<&Term> {
+        self.defs.iter().find(|(x, _)| x == name).map(|(_, t)| t)
+    }
+
+    /// names defined so far, in the order of their definition
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.defs.iter().map(|(x, _)| x.as_str())
+    }
+
+    /// replaces the free variables of t that are defined by their terms
+    pub fn expand(&self, t: &Term) -> Term {
+        t.free_vars()
+            .iter()
+            .filter_map(|x| self.get(x).map(|d| (x, d)))
+            .fold(t.clone(), |t, (x, d)| t.subst(x, d))
+    } //expand
+
+    /// parses a term that may use the defined names
+    pub fn parse(&self, src: &str) -> Result<Term, LambdaError> {
+        Ok(self.expand(&parse_term(src)?))
+    }
+
+    /// Parses and normalizes a term in normal order, giving up after
+    /// [DEFAULT_MAX_STEPS] steps.
+    pub fn eval(&self, src: &str) -> Result<Term, LambdaError> {
+        normalize(&self.parse(src)?, Strategy::NormalOrder, DEFAULT_MAX_STEPS)
+    }
+} // impl Prelude
@@ -132,24 +132,44 @@
 use std::fmt::{Display, Formatter, Result};
 use std::io::{self, Read, Write};
 use Expr::*;
+use Token::*;
 // online calculator in Rust, with shift-reduce parser
 
 /// Abstract Syntax (AST) type for arithmetic expressions. Smart pointer
 /// [Box] is required to define recursive structures.  One drawback of
 /// Rust is that Box blocks nested pattern matching, so sometimes
 /// nested `match` expressions are required.
+///
+/// Cloning, comparing, hashing and dropping a tree are done with an explicit
+/// stack instead of recursion, so even a sum of 100000 terms nested to the
+/// left does not overflow the call stack.  Because of the [Drop] impl,
+/// subtrees cannot be moved out of an `Expr` by a pattern: use
+/// [std::mem::take] on them instead.
+///
+/// The type of numbers defaults to i32, which the evaluator ([eval]) and
+/// the AM16 compiler work with.  Any other [number::Number] type can be
+/// parsed with [parse_number] and evaluated with [number::evaluate].
 #[derive(Debug)]
-pub enum Expr {
-    Val(i32),                   // i32 is type for 32 bit signed ints
-    Plus(Box<Expr>, Box<Expr>), // recursion requires smart pointer
-    Minus(Box<Expr>, Box<Expr>),
-    Times(Box<Expr>, Box<Expr>), // Box is like unique_ptr in C++
-    Divide(Box<Expr>, Box<Expr>),
-    Mod(Box<Expr>, Box<Expr>),
-    Neg(Box<Expr>),
-    Sym(char),
-    EOF,
-    Dummy,
+pub enum Expr<N = i32> {
+    Val(N),                     // N is i32 (32 bit signed ints) by default
+    Plus(Box<Expr<N>>, Box<Expr<N>>), // recursion requires smart pointer
+    Minus(Box<Expr<N>>, Box<Expr<N>>),
+    Times(Box<Expr<N>>, Box<Expr<N>>), // Box is like unique_ptr in C++
+    Divide(Box<Expr<N>>, Box<Expr<N>>),
+    Mod(Box<Expr<N>>, Box<Expr<N>>),
+    Pow(Box<Expr<N>>, Box<Expr<N>>), // a^b, right-associative
+    Neg(Box<Expr<N>>),
+    Var(String),                           // variable
+    Let(String, Box<Expr<N>>, Box<Expr<N>>),     // let x = e1 in e2
+    Less(Box<Expr<N>>, Box<Expr<N>>),            // comparisons evaluate to 1 or 0
+    LessEq(Box<Expr<N>>, Box<Expr<N>>),
+    Equal(Box<Expr<N>>, Box<Expr<N>>),
+    NotEqual(Box<Expr<N>>, Box<Expr<N>>),
+    And(Box<Expr<N>>, Box<Expr<N>>), // && and || short-circuit
+    Or(Box<Expr<N>>, Box<Expr<N>>),
+    Not(Box<Expr<N>>),
+    If(Box<Expr<N>>, Box<Expr<N>>, Box<Expr<N>>), // if c then a else b
+    Call(String, Vec<Expr<N>>), // f(a, b), of a function in a program::Program
 } // Expr enum
 
 impl Expr {
@@ -158,82 +178,623 @@ impl Expr {
         eval(self)
     }
 
-    /// determines if expr is a shallow token, produced by the lexical
-    /// tokenizer before parsing.
+    /// determines if expr is a leaf that used to double as a token, before
+    /// tokens got their own [Token] type.
+    #[deprecated(note = "the lexer now produces Token values")]
     pub fn is_token(&self) -> bool {
-        match self {
-            Val(_) | Sym(_) | EOF | Dummy => true,
-            _ => false,
-        } //match
+        matches!(self, Val(_) | Var(_))
     } //is_token
 
-    /// cloning the entire tree is expensive but a token is shallow and can
-    /// be copied.  Non-token expressions are cloned to `Dummy`.
+    /// Copies a leaf.  Other expressions are cloned to `Val(0)`: use
+    /// [Clone] to copy an entire tree.
+    #[deprecated(note = "Expr implements Clone")]
     pub fn clone_token(&self) -> Self {
         match self {
             Val(n) => Val(*n),
-            Sym(c) => Sym(*c),
-            EOF => EOF,
-            _ => Dummy, // everything else clones to Dummy
+            Var(x) => Var(x.clone()),
+            _ => Val(0),
         } //match
     } //clone_token
 } // method-style implementations
 
+impl<N> Expr<N> {
+    /// immediate subexpressions, from left to right
+    pub fn children(&self) -> Vec<&Expr<N>> {
+        match self {
+            Neg(a) | Not(a) => vec![a],
+            Plus(a, b) | Minus(a, b) | Times(a, b) | Divide(a, b) | Mod(a, b) | Pow(a, b) => {
+                vec![a, b]
+            }
+            Less(a, b) | LessEq(a, b) | Equal(a, b) | NotEqual(a, b) | And(a, b) | Or(a, b) => {
+                vec![a, b]
+            }
+            Let(_, a, b) => vec![a, b],
+            If(c, a, b) => vec![c, a, b],
+            Call(_, args) => args.iter().collect(),
+            _ => vec![],
+        }
+    } //children
+
+    /// mutable references to the immediate subexpressions, from left to right
+    pub fn children_mut(&mut self) -> Vec<&mut Expr<N>> {
+        match self {
+            Neg(a) | Not(a) => vec![a],
+            Plus(a, b) | Minus(a, b) | Times(a, b) | Divide(a, b) | Mod(a, b) | Pow(a, b) => {
+                vec![a, b]
+            }
+            Less(a, b) | LessEq(a, b) | Equal(a, b) | NotEqual(a, b) | And(a, b) | Or(a, b) => {
+                vec![a, b]
+            }
+            Let(_, a, b) => vec![a, b],
+            If(c, a, b) => vec![c, a, b],
+            Call(_, args) => args.iter_mut().collect(),
+            _ => vec![],
+        }
+    } //children_mut
+
+    /// Iterates over all nodes of the tree in preorder, without recursion.
+    pub fn iter(&self) -> Nodes<'_, N> {
+        Nodes { stack: vec![self] }
+    }
+
+    // compares the nodes without their children
+    fn shallow_eq(&self, other: &Expr<N>) -> bool
+    where
+        N: PartialEq,
+    {
+        match (self, other) {
+            (Val(a), Val(b)) => a == b,
+            (Var(x), Var(y)) | (Let(x, ..), Let(y, ..)) => x == y,
+            (Call(f, a), Call(g, b)) => f == g && a.len() == b.len(),
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    } //shallow_eq
+
+    // A node like this one, with the given children in order.  Val nodes
+    // cannot be converted here and are left to the caller.
+    fn with_children<M>(&self, mut kids: impl Iterator<Item = Expr<M>>) -> Expr<M> {
+        let mut next = || Box::new(kids.next().unwrap_or(Var(String::new())));
+        match self {
+            Neg(_) => Neg(next()),
+            Not(_) => Not(next()),
+            Plus(..) => Plus(next(), next()),
+            Minus(..) => Minus(next(), next()),
+            Times(..) => Times(next(), next()),
+            Divide(..) => Divide(next(), next()),
+            Mod(..) => Mod(next(), next()),
+            Pow(..) => Pow(next(), next()),
+            Less(..) => Less(next(), next()),
+            LessEq(..) => LessEq(next(), next()),
+            Equal(..) => Equal(next(), next()),
+            NotEqual(..) => NotEqual(next(), next()),
+            And(..) => And(next(), next()),
+            Or(..) => Or(next(), next()),
+            Let(x, ..) => Let(x.clone(), next(), next()),
+            If(..) => If(next(), next(), next()),
+            Call(f, args) => Call(f.clone(), args.iter().map(|_| *next()).collect()),
+            Var(x) => Var(x.clone()),
+            Val(_) => Var(String::new()),
+        }
+    } //with_children
+
+    /// Converts the numbers of the tree with f, keeping its shape, or
+    /// returns an error of f.  Like [Clone], this needs no recursion.
+    pub fn try_map<M, E>(
+        &self,
+        mut f: impl FnMut(&N) -> std::result::Result<M, E>,
+    ) -> std::result::Result<Expr<M>, E> {
+        // Nodes in reverse preorder come after all of their descendants,
+        // whose copies are then on top of the stack, the first child topmost.
+        let nodes: Vec<&Expr<N>> = self.iter().collect();
+        let mut built: Vec<Expr<M>> = Vec::new();
+        for e in nodes.into_iter().rev() {
+            if let Val(x) = e {
+                built.push(Val(f(x)?));
+                continue;
+            }
+            let n = e.children().len();
+            let kids = built.split_off(built.len() - n);
+            built.push(e.with_children(kids.into_iter().rev()));
+        }
+        Ok(built.pop().unwrap_or(Var(String::new())))
+    } //try_map
+} // generic methods
+
+/// Preorder iterator over the nodes of an [Expr], see [Expr::iter]
+pub struct Nodes<'a, N = i32> {
+    stack: Vec<&'a Expr<N>>,
+}
+
+impl<'a, N> Iterator for Nodes<'a, N> {
+    type Item = &'a Expr<N>;
+    fn next(&mut self) -> Option<&'a Expr<N>> {
+        let e = self.stack.pop()?;
+        self.stack.extend(e.children().into_iter().rev());
+        Some(e)
+    }
+} // impl Iterator for Nodes
+
 /// checks if expr is a proper AST expression, and not just a token pre-parsing.
-/// This function is not equivalent to `!.is_token` because `Val(_)` is both a
-/// token and a proper expression.
+/// Now that tokens have their own [Token] type, this is always true.
+#[deprecated(note = "every Expr is a proper expression")]
 pub fn proper(e: &Expr) -> bool {
-    match e {
-        Sym(_) | EOF | Dummy => false,
-        _ => true,
-    }
+    true
 } //proper
 
 /// Eval function evaluates to an [Option] type. Further demonstrates
 /// monadic error handling, which is the only form of error handling
-/// in Rust.
+/// in Rust.  Arithmetic overflow, like division by zero, results in None
+/// (see [eval_mode] for other ways of handling overflow).  Expressions
+/// other than arithmetic are handed over to [eval_with].
 pub fn eval(e: &Expr) -> Option<i32> {
     match e {
-        Val(x) => Some(*x),                // x is a ref so has to be deref'ed
-        Neg(x) => eval(x).map(|a| -1 * a), //& does deref coercion on Box
-        Plus(x, y) => eval(x).zip(eval(y)).map(|(a, b)| a + b),
-        Minus(x, y) => eval(x).zip(eval(y)).map(|(a, b)| a - b),
-        Times(x, y) => eval(x).zip(eval(y)).map(|(a, b)| a * b),
-        Divide(x, y) => eval(y).and_then(|b| if b != 0 { eval(x).map(|a| a / b) } else { None }),
-        Mod(x, y) => eval(y).and_then(|b| if b != 0 { eval(x).map(|a| a % b) } else { None }),
-        _ => None,
+        Val(x) => Some(*x),                       // x is a ref so has to be deref'ed
+        Neg(x) => eval(x).and_then(i32::checked_neg), //& does deref coercion on Box
+        Plus(x, y) => eval(x).zip(eval(y)).and_then(|(a, b)| a.checked_add(b)),
+        Minus(x, y) => eval(x).zip(eval(y)).and_then(|(a, b)| a.checked_sub(b)),
+        Times(x, y) => eval(x).zip(eval(y)).and_then(|(a, b)| a.checked_mul(b)),
+        Divide(x, y) => eval(y).and_then(|b| eval(x).and_then(|a| a.checked_div(b))),
+        Mod(x, y) => eval(y).filter(|b| *b != 0).and_then(|b| eval(x).map(|a| a.wrapping_rem(b))),
+        _ => eval_with(e, &Env::new()).ok(),
     } //match
 } //eval
 
+/// How [eval_mode] performs arithmetic on i32 values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithMode {
+    /// overflow is reported as [EvalError::Overflow]
+    Checked,
+    /// results wrap around in two's complement, as in release builds
+    Wrapping,
+    /// results are clamped to i32::MIN and i32::MAX
+    Saturating,
+    /// values are truncated to 16 bits and wrap around like the int16
+    /// registers of AM16, so results match what the VM computes.
+    Am16,
+}
+
+/// Errors that can occur while evaluating an expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    DivideByZero,
+    Overflow,
+    /// variable that is not bound by a `let` or the environment
+    Unbound(String),
+    /// operation that is only defined for integers, such as a power of a
+    /// [number::Rational] with a fractional exponent
+    NotAnInteger,
+    /// call of a function that the program does not define
+    UnknownFunction(String),
+    /// call with the wrong number of arguments: the function and how many
+    /// parameters it has
+    WrongArity(String, usize),
+    /// calls nested deeper than [program::MAX_CALL_DEPTH]
+    RecursionLimit,
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            EvalError::DivideByZero => write!(f, "division by zero"),
+            EvalError::Overflow => write!(f, "arithmetic overflow"),
+            EvalError::Unbound(x) => write!(f, "unbound variable {}", x),
+            EvalError::NotAnInteger => write!(f, "operand is not an integer"),
+            EvalError::UnknownFunction(g) => write!(f, "unknown function {}", g),
+            EvalError::WrongArity(g, n) => write!(f, "function {} takes {} arguments", g, n),
+            EvalError::RecursionLimit => {
+                write!(f, "calls nested deeper than {}", program::MAX_CALL_DEPTH)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl Arithmetic<i32> for ArithMode {
+    // value of a literal: only AM16 mode truncates
+    fn literal(&self, n: &i32) -> i32 {
+        match self {
+            ArithMode::Am16 => *n as i16 as i32,
+            _ => *n,
+        }
+    }
+
+    // Applies a binary operator, named by the same char as its token.
+    // Division by zero is an error in every mode.  The remainder of
+    // i32::MIN % -1 is 0 even though Rust's checked_rem treats it as overflow.
+    fn binop(&self, op: char, a: &i32, b: &i32) -> std::result::Result<i32, EvalError> {
+        let (a, b) = (*a, *b);
+        if op == '^' {
+            return self.power(a, b);
+        }
+        if (op == '/' || op == '%') && b == 0 {
+            return Err(EvalError::DivideByZero);
+        }
+        match self {
+            ArithMode::Checked => match op {
+                '+' => a.checked_add(b),
+                '-' => a.checked_sub(b),
+                '*' => a.checked_mul(b),
+                '/' => a.checked_div(b),
+                _ => Some(a.wrapping_rem(b)),
+            }
+            .ok_or(EvalError::Overflow),
+            ArithMode::Wrapping => Ok(match op {
+                '+' => a.wrapping_add(b),
+                '-' => a.wrapping_sub(b),
+                '*' => a.wrapping_mul(b),
+                '/' => a.wrapping_div(b),
+                _ => a.wrapping_rem(b),
+            }),
+            ArithMode::Saturating => Ok(match op {
+                '+' => a.saturating_add(b),
+                '-' => a.saturating_sub(b),
+                '*' => a.saturating_mul(b),
+                '/' => a.saturating_div(b),
+                _ => a.wrapping_rem(b),
+            }),
+            ArithMode::Am16 => {
+                let (a, b) = (a as i16, b as i16);
+                Ok(match op {
+                    '+' => a.wrapping_add(b),
+                    '-' => a.wrapping_sub(b),
+                    '*' => a.wrapping_mul(b),
+                    '/' => a.wrapping_div(b),
+                    _ => a.wrapping_rem(b),
+                } as i32)
+            }
+        } //match
+    } //binop
+
+    fn neg(&self, a: &i32) -> std::result::Result<i32, EvalError> {
+        self.binop('-', &0, a)
+    }
+
+    // Evaluates a comparison.  In AM16 mode, as on the VM, `a<b` is
+    // decided by the sign of the 16-bit difference a-b, which wraps around
+    // when the operands are far apart.
+    fn compare(&self, op: &str, a: &i32, b: &i32) -> bool {
+        let (a, b) = (*a, *b);
+        match self {
+            ArithMode::Am16 => {
+                let (a, b) = (a as i16, b as i16);
+                match op {
+                    "<" => a.wrapping_sub(b) < 0,
+                    "<=" => b.wrapping_sub(a) >= 0,
+                    "==" => a == b,
+                    _ => a != b,
+                }
+            }
+            _ => match op {
+                "<" => a < b,
+                "<=" => a <= b,
+                "==" => a == b,
+                _ => a != b,
+            },
+        }
+    } //compare
+} // impl Arithmetic for ArithMode
+
+impl ArithMode {
+    // Raises a to the power b.  A negative exponent gives 1/a^-b, which
+    // truncates to 0 unless a is 1 or -1, and is a division by zero when a
+    // is 0.  In AM16 mode the power is computed by repeated wrapping
+    // multiplication before the division, as on the VM.
+    fn power(self, a: i32, b: i32) -> std::result::Result<i32, EvalError> {
+        if self == ArithMode::Am16 {
+            let (a, b) = (a as i16, b as i16);
+            let p = a.wrapping_pow(b.unsigned_abs() as u32) as i32;
+            return if b < 0 { self.binop('/', &1, &p) } else { Ok(p) };
+        }
+        if b < 0 {
+            return match a {
+                0 => Err(EvalError::DivideByZero),
+                1 => Ok(1),
+                -1 => Ok(if b % 2 == 0 { 1 } else { -1 }),
+                _ => Ok(0),
+            };
+        }
+        let b = b as u32;
+        match self {
+            ArithMode::Checked => a.checked_pow(b).ok_or(EvalError::Overflow),
+            ArithMode::Saturating => Ok(a.saturating_pow(b)),
+            _ => Ok(a.wrapping_pow(b)),
+        }
+    } //power
+} // impl ArithMode
+
+/// Environment of variable bindings used by [eval_with].  Bindings are
+/// kept in a vector: a later binding of the same name shadows an earlier
+/// one, which is how nested `let` expressions are scoped.  The values are
+/// i32 unless another [number::Number] type is given.
+#[derive(Debug, Clone, Default)]
+pub struct Env<N = i32> {
+    bindings: Vec<(String, N)>,
+}
+
+impl<N: Clone> Env<N> {
+    /// creates an empty environment
+    pub fn new() -> Self {
+        Env { bindings: Vec::new() }
+    }
+
+    /// binds (or re-binds) a variable
+    pub fn bind(&mut self, x: &str, v: N) {
+        self.bindings.push((x.to_owned(), v));
+    }
+
+    /// value of the most recent binding of x
+    pub fn lookup(&self, x: &str) -> Option<N> {
+        self.bindings.iter().rev().find(|(y, _)| y == x).map(|(_, v)| v.clone())
+    }
+} // impl Env
+
+/// Evaluates an expression using the given arithmetic mode, returning
+/// an [EvalError] on division by zero, or on overflow in
+/// [ArithMode::Checked] mode.
+pub fn eval_mode(e: &Expr, mode: ArithMode) -> std::result::Result<i32, EvalError> {
+    eval_scoped(e, &mode, &mut Env::new(), &[], 0)
+} //eval_mode
+
+/// Evaluates an expression that may contain free variables bound in
+/// the environment, with checked arithmetic.
+pub fn eval_with(e: &Expr, env: &Env) -> std::result::Result<i32, EvalError> {
+    eval_with_mode(e, env, ArithMode::Checked)
+}
+
+/// Evaluates an expression in an environment using the given arithmetic
+/// mode.
+pub fn eval_with_mode(e: &Expr, env: &Env, mode: ArithMode) -> std::result::Result<i32, EvalError> {
+    eval_scoped(e, &mode, &mut env.clone(), &[], 0)
+}
+
+// Evaluator behind eval_mode, eval_with and number::evaluate, computing
+// with the given arithmetic.  `let` pushes a binding onto env for the
+// evaluation of its body and pops it afterwards, and calls go to the
+// functions defs, with depth counting the calls in progress.  Comparisons
+// and logical operators give 1 for true and 0 for false.
+pub(crate) fn eval_scoped<N: Number>(
+    e: &Expr<N>,
+    arith: &impl Arithmetic<N>,
+    env: &mut Env<N>,
+    defs: &[program::Function<N>],
+    depth: usize,
+) -> std::result::Result<N, EvalError> {
+    let mut ev = |x: &Expr<N>| eval_scoped(x, arith, env, defs, depth);
+    let truth = |b: bool| N::from_i32(b as i32);
+    let zero = N::from_i32(0);
+    match e {
+        Val(x) => Ok(arith.literal(x)),
+        Neg(x) => arith.neg(&ev(x)?),
+        Var(x) => env.lookup(x).ok_or_else(|| EvalError::Unbound(x.clone())),
+        Let(x, e1, e2) => {
+            let v = ev(e1)?;
+            env.bind(x, v);
+            let result = eval_scoped(e2, arith, env, defs, depth);
+            env.bindings.pop();
+            result
+        }
+        And(x, y) => Ok(truth(ev(x)? != zero && ev(y)? != zero)),
+        Or(x, y) => Ok(truth(ev(x)? != zero || ev(y)? != zero)),
+        Not(x) => Ok(truth(ev(x)? == zero)),
+        If(c, x, y) => {
+            if ev(c)? != zero {
+                ev(x)
+            } else {
+                ev(y)
+            }
+        }
+        Call(f, args) => eval_call(f, args, arith, env, defs, depth),
+        // the other operators share one arm, which keeps the stack frame
+        // of this function small
+        _ => {
+            let (op, x, y) = binary_parts(e).expect("a binary operator");
+            let (a, b) = (ev(x)?, ev(y)?);
+            match op {
+                Sym('<') => Ok(truth(arith.compare("<", &a, &b))),
+                Sym(c) => arith.binop(c, &a, &b),
+                Kw(k) => Ok(truth(arith.compare(k, &a, &b))),
+                _ => unreachable!(),
+            }
+        }
+    } //match
+} //eval_scoped
+
+// A call evaluates the body of one of the functions defs in a new
+// environment that only binds its parameters.  It's kept out of
+// eval_scoped, whose stack frame is part of every level of recursion.
+#[inline(never)]
+fn eval_call<N: Number>(
+    f: &str,
+    args: &[Expr<N>],
+    arith: &impl Arithmetic<N>,
+    env: &mut Env<N>,
+    defs: &[program::Function<N>],
+    depth: usize,
+) -> std::result::Result<N, EvalError> {
+    let def = program::lookup(defs, f, args.len())?;
+    if depth >= program::MAX_CALL_DEPTH {
+        return Err(EvalError::RecursionLimit);
+    }
+    let mut frame = Env::new();
+    for (x, a) in def.params.iter().zip(args) {
+        frame.bind(x, eval_scoped(a, arith, env, defs, depth)?);
+    }
+    eval_scoped(&def.body, arith, &mut frame, defs, depth + 1)
+} //eval_call
+
 /////////// Trait implementations for Expr
 
-impl Default for Expr {
-    fn default() -> Expr {
-        Dummy
+impl<N: Default> Default for Expr<N> {
+    fn default() -> Expr<N> {
+        Val(N::default())
     }
 }
 
-impl Display for Expr {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result // required by trait
-    {
+impl<N: Clone> Clone for Expr<N> {
+    fn clone(&self) -> Expr<N> {
+        let copy = self.try_map(|n| Ok::<N, std::convert::Infallible>(n.clone()));
+        match copy {
+            Ok(e) => e,
+            Err(never) => match never {},
+        }
+    } //clone
+}
+
+impl<N: PartialEq> PartialEq for Expr<N> {
+    // two trees are equal when their nodes are pairwise equal in preorder,
+    // since the nodes determine their number of children
+    fn eq(&self, other: &Expr<N>) -> bool {
+        let (mut a, mut b) = (self.iter(), other.iter());
+        loop {
+            match (a.next(), b.next()) {
+                (None, None) => return true,
+                (Some(x), Some(y)) if x.shallow_eq(y) => {}
+                _ => return false,
+            }
+        }
+    } //eq
+}
+
+impl<N: Eq> Eq for Expr<N> {}
+
+impl<N: std::hash::Hash> std::hash::Hash for Expr<N> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        for e in self.iter() {
+            std::mem::discriminant(e).hash(state);
+            match e {
+                Val(n) => n.hash(state),
+                Var(x) | Let(x, ..) => x.hash(state),
+                Call(f, args) => {
+                    f.hash(state);
+                    args.len().hash(state);
+                }
+                _ => {}
+            }
+        }
+    } //hash
+}
+
+impl<N> Drop for Expr<N> {
+    // The children are moved to a stack before the node is dropped, so
+    // that dropping them does not recurse.  They are replaced by an empty
+    // Var, which needs nothing from N.
+    fn drop(&mut self) {
+        let leaf = || Var(String::new());
+        let mut pending: Vec<Expr<N>> = Vec::new();
+        pending.extend(self.children_mut().into_iter().map(|c| std::mem::replace(c, leaf())));
+        while let Some(mut e) = pending.pop() {
+            pending.extend(e.children_mut().into_iter().map(|c| std::mem::replace(c, leaf())));
+        }
+    } //drop
+}
+
+// Operator and operands of a binary expression, with the token of the
+// operator so that printing uses the same precedences as parsing.
+fn binary_parts<N>(e: &Expr<N>) -> Option<(Token, &Expr<N>, &Expr<N>)> {
+    let (op, x, y) = match e {
+        Plus(x, y) => (Sym('+'), x, y),
+        Minus(x, y) => (Sym('-'), x, y),
+        Times(x, y) => (Sym('*'), x, y),
+        Divide(x, y) => (Sym('/'), x, y),
+        Mod(x, y) => (Sym('%'), x, y),
+        Pow(x, y) => (Sym('^'), x, y),
+        Less(x, y) => (Sym('<'), x, y),
+        LessEq(x, y) => (Kw("<="), x, y),
+        Equal(x, y) => (Kw("=="), x, y),
+        NotEqual(x, y) => (Kw("!="), x, y),
+        And(x, y) => (Kw("&&"), x, y),
+        Or(x, y) => (Kw("||"), x, y),
+        _ => return None,
+    };
+    Some((op, x, y))
+} //binary_parts
+
+impl<N: Number> Expr<N> {
+    // Prints the expression inside a context where operators of precedence
+    // below ctx would need parentheses.  The operand on the side opposite
+    // to the associativity of an operator needs them at the same
+    // precedence: the right one of `-` and the left one of `^`.  The
+    // bodies of let and if extend as far right as possible, so they are
+    // only left bare where something like `in` or `)` closes them (ctx 0).
+    fn fmt_prec(&self, ctx: u32, f: &mut Formatter<'_>) -> Result {
+        let unary = UNARY_PRECEDENCE;
+        let op = binary_parts(self).and_then(|(t, _, _)| default_binary(&t));
+        let own = match self {
+            Val(x) if *x < N::from_i32(0) => unary, // printed as a unary minus
+            // a fraction such as 1/2 is printed as a division
+            Val(x) if x.to_string().contains('/') => default_binary(&Sym('/')).map_or(0, |o| o.1),
+            Neg(_) | Not(_) => unary,
+            Let(..) | If(..) => 0,
+            _ => op.map_or(OPERAND_PRECEDENCE, |&(_, precedence, _)| precedence),
+        };
+        let paren = own < ctx;
+        if paren {
+            write!(f, "(")?;
+        }
         match self {
-            Val(x) => write!(f, "{}", x),
-            Plus(x, y) => write!(f, "({}+{})", x, y),
-            Times(x, y) => write!(f, "{}*{}", x, y),
-            Minus(x, y) => write!(f, "({}-{})", x, y),
-            Divide(x, y) => write!(f, "{}/{}", x, y),
-            Mod(x, y) => write!(f, "{}%{}", x, y),
             Neg(x) => {
-                if let Neg(y) = &**x {
-                    write!(f, "{}", y)
-                } else {
-                    write!(f, "-{}", x)
+                write!(f, "-")?;
+                x.fmt_prec(unary, f)?;
+            }
+            Not(x) => {
+                write!(f, "!")?;
+                x.fmt_prec(unary, f)?;
+            }
+            Let(x, e1, e2) => {
+                write!(f, "let {} = ", x)?;
+                e1.fmt_prec(0, f)?;
+                write!(f, " in ")?;
+                e2.fmt_prec(0, f)?;
+            }
+            If(c, x, y) => {
+                write!(f, "if ")?;
+                c.fmt_prec(0, f)?;
+                write!(f, " then ")?;
+                x.fmt_prec(0, f)?;
+                write!(f, " else ")?;
+                y.fmt_prec(0, f)?;
+            }
+            Val(x) => write!(f, "{}", x)?,
+            Var(x) => write!(f, "{}", x)?,
+            Call(g, args) => {
+                write!(f, "{}(", g)?;
+                for (i, a) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    a.fmt_prec(0, f)?;
+                }
+                write!(f, ")")?;
+            }
+            _ => {
+                if let (Some((symbol, _, assoc)), Some((_, x, y))) = (op, binary_parts(self)) {
+                    let (left, right) = match assoc {
+                        Assoc::Left => (own, own + 1),
+                        Assoc::Right => (own + 1, own),
+                    };
+                    x.fmt_prec(left, f)?;
+                    write!(f, "{}", symbol)?;
+                    y.fmt_prec(right, f)?;
                 }
             }
-            Sym(s) => write!(f, " {} ", s),
-            EOF => write!(f, " EOF "),
-            Dummy => write!(f, " Dummy "),
         } //match
+        if paren {
+            write!(f, ")")?;
+        }
+        Ok(())
+    } //fmt_prec
+}
+
+/// Prints an expression in the syntax accepted by [parse], with only the
+/// parentheses that are needed, so that parsing the output gives back the
+/// same tree.  Negative constants, which the parser builds with `Neg`
+/// instead, are the one exception: `Val(-3)` prints as `-3`.  So are
+/// fractions of [number::Rational], which print as a division.
+impl<N: Number> Display for Expr<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result // required by trait
+    {
+        self.fmt_prec(0, f)
     }
 } // impl Display for Expr
   // no ability to deep-pattern match inside a Box/Rc
@@ -245,152 +806,828 @@ pub fn eval_interactive() // interpreter
     io::stdout().flush().unwrap();
     let mut input = String::new();
     if let Ok(_) = io::stdin().read_line(&mut input) {
-        let tokens = lex(&input[0..input.len() - 1].trim());
-        //println!("tokens: {:?}",&tokens);
-        let exp = parse(&tokens);
-        exp.and_then(|e| {
-            eval(&e).map(|n| {
-                println!("Value of {} = {}", &e, n);
-            })
-        });
+        match parse_str(input.trim()) {
+            Ok(e) => match eval_mode(&e, ArithMode::Checked) {
+                Ok(n) => println!("Value of {} = {}", &e, n),
+                Err(err) => eprintln!("{}", err),
+            },
+            Err(err) => eprintln!("{}", err),
+        }
     } // if let Ok(n)
-} // - evals instead of compiles
+} // - evals instead of compiles, see the repl module for a loop
+
+/// Tokens produced by the lexer ([lex] and [tokenize]) and consumed by the
+/// parser.  Operators of one character, such as `+` and `(`, are `Sym`
+/// tokens; keywords and longer operators such as `<=` and `&&` are `Kw`
+/// tokens.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Token {
+    /// integer literal, as written
+    Num(String),
+    /// numeric literal with a decimal point, such as `3.25`, as written
+    Decimal(String),
+    Ident(String),
+    Sym(char),
+    Kw(&'static str),
+    EOF,
+    /// placeholder emitted by [lex] at a lexical error
+    Dummy,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Num(x) | Decimal(x) | Ident(x) => write!(f, "{}", x),
+            Sym(c) => write!(f, "{}", c),
+            Kw(k) => write!(f, "{}", k),
+            EOF => write!(f, "EOF"),
+            Dummy => write!(f, "Dummy"),
+        }
+    }
+} // impl Display for Token
 
 ////////////////////////////////////////////////////////////////////////////
 /// Simple String Tokenizer.  Takes a string slice and generates a [Vec]tor
-/// of tokens such as `Sym("+")`, `Val(3)`, etc.
-pub fn lex(inp: &str) -> Vec<Expr> {
+/// of tokens such as `Sym('+')`, `Num("3")`, etc.  This lenient version
+/// never fails: at the first lexical error it emits a `Dummy` token,
+/// which [parse] rejects.  Use [tokenize] to find out what went wrong.
+pub fn lex(inp: &str) -> Vec<Token> {
+    let (spanned, err) = scan(inp, &ParserConfig::<i32>::default());
+    let mut tokens: Vec<Token> = spanned.into_iter().map(|s| s.token).collect();
+    if err.is_some() {
+        tokens.insert(tokens.len() - 1, Dummy); // before EOF
+    }
+    tokens
+} //lexer
+
+/// A token along with its location in the input: `start` is the character
+/// offset of its first character and `end` the offset just past its last.
+#[derive(Debug, Clone)]
+pub struct Spanned {
+    pub token: Token,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The kinds of errors found by [tokenize]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexErrorKind {
+    /// character that cannot start any token
+    UnknownChar(char),
+    /// numeric literal too large for the type of numbers, an i32 for
+    /// [tokenize]
+    Overflow(String),
+    /// decimal literal with more digits than the type of numbers can hold
+    Precision(String),
+}
+
+/// Lexical error with the character span of the offending text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexError {
+    pub start: usize,
+    pub end: usize,
+    pub kind: LexErrorKind,
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match &self.kind {
+            LexErrorKind::UnknownChar(c) => {
+                write!(f, "lexical error at offset {}: unknown character {:?}", self.start, c)
+            }
+            LexErrorKind::Overflow(s) => write!(
+                f,
+                "lexical error at offset {}: number {} is too large",
+                self.start, s
+            ),
+            LexErrorKind::Precision(s) => write!(
+                f,
+                "lexical error at offset {}: number {} has too many digits",
+                self.start, s
+            ),
+        }
+    }
+} // impl Display for LexError
+
+impl std::error::Error for LexError {}
+
+/// Position-aware tokenizer: produces each token with its span, ending
+/// with `EOF`.  Spaces, tabs and newlines separate tokens.  Numbers are
+/// integers, or `Decimal` tokens when they have a decimal point.
+/// Identifiers start with a letter or `_`, and `let`, `in`, `if`, `then`
+/// and `else` are keywords.  The operators `<= == != && ||` are
+/// represented by `Kw` tokens.  Characters other than these, digits,
+/// whitespace and `+ - * / % ^ ( ) = < ! , ;` are rejected, as are numbers
+/// that overflow an i32.
+pub fn tokenize(inp: &str) -> std::result::Result<Vec<Spanned>, LexError> {
+    tokenize_with(inp, &ParserConfig::<i32>::default())
+} //tokenize
+
+/// Tokenizer that recognizes the operator symbols of the given
+/// configuration, the longest symbol winning when several match.  Numbers
+/// must fit the type N of the configuration.
+pub fn tokenize_with<N: Number>(
+    inp: &str,
+    config: &ParserConfig<N>,
+) -> std::result::Result<Vec<Spanned>, LexError> {
+    match scan(inp, config) {
+        (tokens, None) => Ok(tokens),
+        (_, Some(err)) => Err(err),
+    }
+} //tokenize_with
+
+// Scans input up to the first lexical error, returning the tokens found
+// so far (always ending with EOF) and the error, if any.
+fn scan<N: Number>(inp: &str, config: &ParserConfig<N>) -> (Vec<Spanned>, Option<LexError>) {
+    let symbols = config.symbols();
     let input: Vec<char> = inp.chars().collect();
-    let mut tokens: Vec<Expr> = Vec::new();
+    let mut tokens: Vec<Spanned> = Vec::new();
+    let mut error = None;
     let mut i: usize = 0;
-    while i < input.len() {
-        let mut c = input[i];
+    while i < input.len() && error.is_none() {
+        let start = i;
+        let c = input[i];
         i += 1; // get one char
-        let mut isnum = false;
-        let mut n = 0;
-        while c.is_digit(10) && i <= input.len() {
-            isnum = true;
-            n = n * 10 + c.to_digit(10).unwrap();
-            if i < input.len() {
-                c = input[i];
+        if c.is_ascii_digit() {
+            while i < input.len() && input[i].is_ascii_digit() {
                 i += 1;
+            }
+            // a decimal point must be followed by a digit
+            if i + 1 < input.len() && input[i] == '.' && input[i + 1].is_ascii_digit() {
+                i += 1;
+                while i < input.len() && input[i].is_ascii_digit() {
+                    i += 1;
+                }
+                // the parser rejects decimals for types that have none
+                let text: String = input[start..i].iter().collect();
+                let whole = text.split('.').next().unwrap_or_default();
+                let kind = if !N::DECIMALS || N::from_decimal(&text).is_some() {
+                    tokens.push(Spanned { token: Decimal(text), start, end: i });
+                    continue;
+                } else if N::from_integer(whole).is_none() {
+                    LexErrorKind::Overflow(text)
+                } else {
+                    LexErrorKind::Precision(text)
+                };
+                error = Some(LexError { start, end: i, kind });
+                continue;
+            }
+            let digits: String = input[start..i].iter().collect();
+            if N::from_integer(&digits).is_some() {
+                tokens.push(Spanned { token: Num(digits), start, end: i });
             } else {
+                error = Some(LexError { start, end: i, kind: LexErrorKind::Overflow(digits) })
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            while i < input.len() && (input[i].is_alphanumeric() || input[i] == '_') {
                 i += 1;
             }
-        } // while numerical digit
-        if isnum {
-            tokens.push(Val(n as i32));
-        }
-        if !c.is_digit(10) && c != ' ' {
-            tokens.push(Sym(c));
+            let word: String = input[start..i].iter().collect();
+            let token = match word.as_str() {
+                "let" => Kw("let"),
+                "in" => Kw("in"),
+                "if" => Kw("if"),
+                "then" => Kw("then"),
+                "else" => Kw("else"),
+                _ => Ident(word),
+            };
+            tokens.push(Spanned { token, start, end: i });
+        } else if let Some(op) = symbols
+            .iter()
+            .find(|op| input[start..].starts_with(&op.chars().collect::<Vec<char>>()))
+        {
+            i = start + op.chars().count();
+            tokens.push(Spanned { token: symbol_token(op), start, end: i });
+        } else if !c.is_whitespace() {
+            error = Some(LexError { start, end: i, kind: LexErrorKind::UnknownChar(c) });
         }
     } //while
-    tokens.push(EOF);
-    return tokens;
-} //lexer
+    tokens.push(Spanned { token: EOF, start: i, end: i });
+    (tokens, error)
+} //scan
 
-/// operator precedence parser
-fn precedence(e: &Expr) -> u32 {
-    match e {
-        Val(_) => 250,
-        Sym('+') => 100,
-        Sym('-') => 100,
-        Sym('*') => 200,
-        Sym('/') => 200,
-        Sym('%') => 200,
-        Sym('u') => 220,
-        Sym('(') => 500,
-        Sym(')') => 10,
-        EOF => 5,
-        _ => 0,
+////////////////////////////////////////////////////////////////////////////
+/// Associativity of a binary operator: `a-b-c` is `(a-b)-c` because `-`
+/// associates to the left, while `a^b^c` is `a^(b^c)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+/// A binary operator known to the parser: its symbol, precedence (higher
+/// binds tighter), associativity and the function that builds the
+/// expression from the two operands.
+#[derive(Debug)]
+pub struct BinaryOp<N = i32> {
+    pub symbol: &'static str,
+    pub precedence: u32,
+    pub assoc: Assoc,
+    pub build: Binary<N>,
+}
+
+/// constructor of a binary expression, such as `Expr::Plus`
+pub type Binary<N> = fn(Box<Expr<N>>, Box<Expr<N>>) -> Expr<N>;
+
+/// A prefix operator such as unary minus, which takes its operand on
+/// the right.  A symbol can be both a prefix and a binary operator, and
+/// it is read as a prefix operator where an operand is expected.
+#[derive(Debug)]
+pub struct PrefixOp<N = i32> {
+    pub symbol: &'static str,
+    pub precedence: u32,
+    pub build: fn(Box<Expr<N>>) -> Expr<N>,
+}
+
+// operators are copied whatever the type of numbers, unlike with derive
+impl<N> Clone for BinaryOp<N> {
+    fn clone(&self) -> Self {
+        *self
     }
 }
-fn prec(a: &Expr, b: char) -> bool {
-    precedence(a) <= precedence(&Sym(b))
+impl<N> Copy for BinaryOp<N> {}
+impl<N> Clone for PrefixOp<N> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
-// assume universal left-associativity.
+impl<N> Copy for PrefixOp<N> {}
 
-fn ateof(e: &Expr) -> bool // at end-of-file predicate
+// The binary operators of the language, as used by ParserConfig::default
+// and by the Display impl of Expr.  The constructors, which depend on the
+// type of numbers, are given by binary_build.
+const BINARY_OPS: &[(&str, u32, Assoc)] = &[
+    ("||", 30, Assoc::Left),
+    ("&&", 40, Assoc::Left),
+    ("<", 60, Assoc::Left),
+    ("<=", 60, Assoc::Left),
+    ("==", 60, Assoc::Left),
+    ("!=", 60, Assoc::Left),
+    ("+", 100, Assoc::Left),
+    ("-", 100, Assoc::Left),
+    ("*", 200, Assoc::Left),
+    ("/", 200, Assoc::Left),
+    ("%", 200, Assoc::Left),
+    ("^", 230, Assoc::Right),
+];
+
+fn binary_build<N>(symbol: &str) -> Binary<N> {
+    match symbol {
+        "||" => Or,
+        "&&" => And,
+        "<" => Less,
+        "<=" => LessEq,
+        "==" => Equal,
+        "!=" => NotEqual,
+        "+" => Plus,
+        "-" => Minus,
+        "*" => Times,
+        "/" => Divide,
+        "%" => Mod,
+        _ => Pow,
+    }
+} //binary_build
+
+// precedence of unary minus and `!`, and of numbers and identifiers
+const UNARY_PRECEDENCE: u32 = 220;
+const OPERAND_PRECEDENCE: u32 = 250;
+
+// the operator of the default configuration that a token stands for
+fn default_binary(t: &Token) -> Option<&'static (&'static str, u32, Assoc)> {
+    BINARY_OPS.iter().find(|(symbol, _, _)| symbol_token(symbol) == *t)
+}
+
+// the token that the lexer produces for an operator symbol
+fn symbol_token(symbol: &'static str) -> Token {
+    let mut chars = symbol.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Sym(c),
+        _ => Kw(symbol),
+    }
+}
+
+/// Operator table used by the lexer and the parser ([tokenize_with] and
+/// [parse_spanned_with]), for expressions over numbers of type N.  The
+/// default configuration has the operators of the language; more can be
+/// registered with [ParserConfig::add_binary] and
+/// [ParserConfig::add_prefix], using symbols made of punctuation.  The
+/// symbols `(`, `)` and `=` and the keywords are reserved, and precedences
+/// should stay between 20 and 250, which are those of `=` and of operands.
+#[derive(Debug, Clone)]
+pub struct ParserConfig<N = i32> {
+    binary: Vec<BinaryOp<N>>,
+    prefix: Vec<PrefixOp<N>>,
+}
+
+impl<N> Default for ParserConfig<N> {
+    fn default() -> Self {
+        let binary = BINARY_OPS.iter().map(|&(symbol, precedence, assoc)| BinaryOp {
+            symbol,
+            precedence,
+            assoc,
+            build: binary_build(symbol),
+        });
+        let prefix = [("-", Neg as fn(_) -> _), ("!", Not)].map(|(symbol, build)| PrefixOp {
+            symbol,
+            precedence: UNARY_PRECEDENCE,
+            build,
+        });
+        ParserConfig {
+            binary: binary.collect(),
+            prefix: prefix.into_iter().collect(),
+        }
+    }
+} // impl Default for ParserConfig
+
+impl<N> ParserConfig<N> {
+    /// configuration without any operators
+    pub fn empty() -> Self {
+        ParserConfig {
+            binary: Vec::new(),
+            prefix: Vec::new(),
+        }
+    }
+
+    /// registers a binary operator, replacing any with the same symbol
+    pub fn add_binary(&mut self, op: BinaryOp<N>) -> &mut Self {
+        self.binary.retain(|o| o.symbol != op.symbol);
+        self.binary.push(op);
+        self
+    }
+
+    /// registers a prefix operator, replacing any with the same symbol
+    pub fn add_prefix(&mut self, op: PrefixOp<N>) -> &mut Self {
+        self.prefix.retain(|o| o.symbol != op.symbol);
+        self.prefix.push(op);
+        self
+    }
+
+    /// the binary operator that a token stands for, if any
+    pub fn binary_op(&self, t: &Token) -> Option<&BinaryOp<N>> {
+        self.binary.iter().find(|o| symbol_token(o.symbol) == *t)
+    }
+
+    /// the prefix operator that a token stands for, if any
+    pub fn prefix_op(&self, t: &Token) -> Option<&PrefixOp<N>> {
+        self.prefix.iter().find(|o| symbol_token(o.symbol) == *t)
+    }
+
+    // Precedence of a lookahead token: the parser reduces the top of its
+    // stack when the lookahead does not bind tighter.  Tokens that start
+    // an operand have the highest precedence.
+    fn precedence(&self, t: &Token) -> u32 {
+        match t {
+            Num(_) | Decimal(_) | Ident(_) => OPERAND_PRECEDENCE,
+            Sym('(') | Kw("let") | Kw("if") => 500,
+            Sym('=') => 20,
+            Kw("in") | Kw("then") | Kw("else") => 15,
+            Sym(')') | Sym(',') => 10,
+            EOF => 5,
+            t => match (self.binary_op(t), self.prefix_op(t)) {
+                (Some(op), _) => op.precedence,
+                (None, Some(_)) => 500,
+                (None, None) => 0,
+            },
+        }
+    } //precedence
+
+    // symbols recognized by the lexer, longest first
+    fn symbols(&self) -> Vec<&'static str> {
+        let ops = self.binary.iter().map(|o| o.symbol);
+        let mut symbols: Vec<&'static str> = ops
+            .chain(self.prefix.iter().map(|o| o.symbol))
+            .chain(["(", ")", "=", ",", ";"])
+            .collect();
+        symbols.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        symbols
+    } //symbols
+} // impl ParserConfig
+
+// The body of a let, like the else branch of an if, extends as far to the
+// right as possible, so it's only reduced when the lookahead is `in`,
+// `then`, `else`, `)` or EOF
+const LET_PRECEDENCE: u32 = 20;
+
+fn ateof(t: &Token) -> bool // at end-of-file predicate
 {
-    match e {
-        EOF => true,
-        _ => false,
+    matches!(t, EOF)
+}
+
+// The parse stack holds expressions built so far and tokens that still
+// wait for their operands.  Numbers and identifiers are pushed as
+// expressions right away.
+enum Item<N> {
+    Ast(Expr<N>),
+    Tok(Token),
+}
+use Item::{Ast, Tok};
+
+impl<N: Number> Item<N> {
+    fn into_expr(self) -> Expr<N> {
+        match self {
+            Ast(e) => e,
+            Tok(_) => Expr::default(),
+        }
+    }
+}
+
+/// What the parser expected to see at the point of a [ParseError].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+    /// a number, `(` or unary `-`; for example after a dangling operator
+    Operand,
+    /// a binary operator, `)` or the end of input
+    Operator,
+    /// a `)` to match an earlier `(`
+    ClosingParen,
+    /// a variable name, after `let`
+    Identifier,
+    /// a specific token such as `=`, `in`, `then` or `else`
+    Token(&'static str),
+    /// an integer, where a decimal literal is not a number of the type
+    /// being parsed
+    Integer,
+}
+
+/// Error produced by [parse_spanned].  The `offset` and `end` give the
+/// character span of the offending token `found` in the input (`EOF` if
+/// the input ended too early).  An unmatched `(` is reported at the
+/// location of the `(`.
+#[derive(Debug)]
+pub struct ParseError {
+    pub offset: usize,
+    pub end: usize,
+    pub found: Token,
+    pub expected: Expected,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let expected = match self.expected {
+            Expected::Operand => String::from("an operand"),
+            Expected::Operator => String::from("an operator"),
+            Expected::ClosingParen => String::from("a matching ')'"),
+            Expected::Identifier => String::from("an identifier"),
+            Expected::Token(t) => format!("'{}'", t),
+            Expected::Integer => String::from("an integer"),
+        };
+        write!(f, "parse error at offset {}: expected {}, found ", self.offset, expected)?;
+        match &self.found {
+            EOF => write!(f, "end of input"),
+            Sym('(') if self.expected == Expected::ClosingParen => write!(f, "unmatched '('"),
+            Sym(c) => write!(f, "'{}'", c),
+            Kw(k) => write!(f, "{}", k),
+            t => write!(f, "{}", t),
+        }
     }
+} // impl Display for ParseError
+
+impl std::error::Error for ParseError {}
+
+// The innermost construct that is still open: `(`, a `let` waiting for
+// its `in`, an `if` waiting for `then` or a `then` waiting for `else`.
+// The bodies of let expressions and else branches (recorded by their `in`
+// and `else`) end implicitly, so they are skipped.
+fn innermost<'t>(open: &[&'t Spanned]) -> Option<&'t Spanned> {
+    open.iter().rev().find(|s| !matches!(s.token, Kw("in") | Kw("else"))).copied()
 }
 
+// token that closes an open construct, Sym(')') being represented by ")"
+fn closer(opener: &Token) -> &'static str {
+    match opener {
+        Kw("let") => "in",
+        Kw("if") => "then",
+        Kw("then") => "else",
+        _ => ")",
+    }
+}
+
+// Index and name of the function of a call `f(a, b)` that is complete on
+// top of the stack: the name followed by `(`, arguments separated by `,`
+// and `)`.
+fn call_start<N>(stack: &[Item<N>]) -> Option<(usize, String)> {
+    if !matches!(stack.last(), Some(Tok(Sym(')')))) {
+        return None;
+    }
+    let mut i = stack.len().checked_sub(2)?;
+    if let Ast(_) = stack[i] {
+        // each argument is preceded by `,` or by the `(`
+        i = i.checked_sub(1)?;
+        while let Tok(Sym(',')) = stack[i] {
+            i = i.checked_sub(2)?;
+            if !matches!(stack[i + 1], Ast(_)) {
+                return None;
+            }
+        }
+    }
+    match (&stack[i], stack.get(i.checked_sub(1)?)?) {
+        (Tok(Sym('(')), Ast(Var(f))) => Some((i - 1, f.clone())),
+        _ => None,
+    }
+} //call_start
+
+// true if the innermost `(` on the stack opens the arguments of a call
+fn in_call<N>(stack: &[Item<N>]) -> bool {
+    match stack.iter().rposition(|item| matches!(item, Tok(Sym('(')))) {
+        Some(i) => i > 0 && matches!(stack[i - 1], Ast(Var(_))),
+        None => false,
+    }
+}
+
+// Checks that the lookahead token can follow the top of the parse stack:
+// an operand is needed at the start, after an operator and after `(`,
+// otherwise a binary operator, `)` or EOF must follow.  Prefix operators
+// count as operands here.  A `let` must be followed by a variable and `=`,
+// and `in`, `then`, `else` and `)` must close the innermost open construct.
+// A `(` right after a variable starts a call, whose arguments are
+// separated by `,`.  Called before each shift and when the parser gets
+// stuck.
+fn check_next<N>(
+    stack: &[Item<N>],
+    lookahead: &Spanned,
+    open: &[&Spanned],
+    config: &ParserConfig<N>,
+) -> std::result::Result<(), ParseError> {
+    let operand_expected = !matches!(stack.last(), Some(Ast(_)));
+    let closing = innermost(open).map(|s| closer(&s.token));
+    let (ok, expected) = match (stack, &lookahead.token) {
+        ([.., Tok(Kw("let"))], t) => (matches!(t, Ident(_)), Expected::Identifier),
+        ([.., Tok(Kw("let")), Ast(Var(_))], t) => (matches!(t, Sym('=')), Expected::Token("=")),
+        ([.., Ast(Var(_))], Sym('(')) => (true, Expected::Operator),
+        ([.., Ast(Var(_)), Tok(Sym('('))], Sym(')')) => (true, Expected::Operator),
+        (_, Num(_) | Decimal(_) | Ident(_) | Sym('(') | Kw("let") | Kw("if")) => {
+            (operand_expected, Expected::Operator)
+        }
+        (_, t) if config.prefix_op(t).is_some() => {
+            let binary = config.binary_op(t).is_some();
+            (operand_expected || binary, Expected::Operator)
+        }
+        _ if operand_expected => (false, Expected::Operand),
+        (_, EOF) => (true, Expected::Operator),
+        (_, t) if config.binary_op(t).is_some() => (true, Expected::Operator),
+        (_, Sym(')')) if closing == Some(")") => (true, Expected::Operator),
+        (_, Sym(',')) if closing == Some(")") && in_call(stack) => (true, Expected::Operator),
+        (_, Kw(k)) if closing == Some(*k) => (true, Expected::Operator),
+        _ => match closing {
+            Some(")") => (false, Expected::Operator),
+            Some(k) => (false, Expected::Token(k)),
+            None => (false, Expected::Operator),
+        },
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(ParseError {
+            offset: lookahead.start,
+            end: lookahead.end,
+            found: lookahead.token.clone(),
+            expected,
+        })
+    }
+} //check_next
+
 /// This function takes a vector of tokens produced by the [lex] function
 /// and returns an Expr inside an Option: Some(AST) or None if parsing
-/// failed. The function is defined using "slice patterns". Also note
-/// the while loop: recursion is generally discouraged in Rust.  However,
-/// in dealing with recursive Expr trees it is OK because these trees will
-/// not be large.
-pub fn parse(tokens: &Vec<Expr>) -> Option<Expr> {
-    let mut stack: Vec<Expr> = Vec::new();
+/// failed. Use [parse_str] to find out why parsing failed.  The token
+/// index takes the place of the character offset in each span.
+pub fn parse(tokens: &[Token]) -> Option<Expr> {
+    let spanned: Vec<Spanned> = tokens
+        .iter()
+        .enumerate()
+        .map(|(i, t)| Spanned { token: t.clone(), start: i, end: i + 1 })
+        .collect();
+    parse_spanned(&spanned).ok()
+} //parse
+
+/// Either kind of error that can occur when parsing a string
+#[derive(Debug)]
+pub enum SyntaxError {
+    Lex(LexError),
+    Parse(ParseError),
+}
+
+impl SyntaxError {
+    /// character span (start, end) of the error in the input
+    pub fn span(&self) -> (usize, usize) {
+        match self {
+            SyntaxError::Lex(e) => (e.start, e.end),
+            SyntaxError::Parse(e) => (e.offset, e.end),
+        }
+    }
+} // impl SyntaxError
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            SyntaxError::Lex(e) => write!(f, "{}", e),
+            SyntaxError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+impl From<LexError> for SyntaxError {
+    fn from(e: LexError) -> Self {
+        SyntaxError::Lex(e)
+    }
+}
+
+impl From<ParseError> for SyntaxError {
+    fn from(e: ParseError) -> Self {
+        SyntaxError::Parse(e)
+    }
+}
+
+/// Tokenizes and parses a string, returning an error that locates
+/// the problem in the input if either step failed.
+pub fn parse_str(inp: &str) -> std::result::Result<Expr, SyntaxError> {
+    parse_str_with(inp, &ParserConfig::default())
+} //parse_str
+
+/// Tokenizes and parses a string with the operators of the given
+/// configuration, see [tokenize_with] and [parse_spanned_with].
+pub fn parse_str_with<N: Number>(
+    inp: &str,
+    config: &ParserConfig<N>,
+) -> std::result::Result<Expr<N>, SyntaxError> {
+    let tokens = tokenize_with(inp, config)?;
+    Ok(parse_spanned_with(&tokens, config)?)
+} //parse_str_with
+
+/// Parses a string into an expression over another type of numbers, such
+/// as `parse_number::<f64>("3.25*2")`.  Decimal literals are an error for
+/// types that cannot represent them, such as i32.
+pub fn parse_number<N: Number>(inp: &str) -> std::result::Result<Expr<N>, SyntaxError> {
+    parse_str_with(inp, &ParserConfig::default())
+}
+
+/// The shift-reduce parser behind [parse] and [parse_str], which takes
+/// the output of [tokenize].  It's defined using "slice patterns". Also
+/// note the while loop: recursion is generally discouraged in Rust.
+/// However, in dealing with recursive Expr trees it is OK because these
+/// trees will not be large.  The tokens that open a construct, such as `(`
+/// and `let`, are also kept on a separate stack for error reporting.
+pub fn parse_spanned(tokens: &[Spanned]) -> std::result::Result<Expr, ParseError> {
+    parse_spanned_with(tokens, &ParserConfig::default())
+} //parse_spanned
+
+/// Shift-reduce parser driven by an operator table.  The top of the stack
+/// is reduced by a binary operator when the lookahead has a lower
+/// precedence, or the same precedence and the operator associates to the
+/// left; otherwise the lookahead is shifted, and a right operand like the
+/// `b^c` of `a^b^c` is reduced first.  This is precedence climbing, done
+/// with the stack of the shift-reduce loop instead of recursion.
+pub fn parse_spanned_with<N: Number>(
+    tokens: &[Spanned],
+    config: &ParserConfig<N>,
+) -> std::result::Result<Expr<N>, ParseError> {
+    let mut stack: Vec<Item<N>> = Vec::new();
+    let mut open: Vec<&Spanned> = Vec::new();
     let mut ti: usize = 0; // indexes tokens
-    let mut lookahead = &tokens[ti];
-    while !(ateof(lookahead) && stack.len() == 1) {
+    let mut lookahead = &tokens[ti].token;
+    while !(ateof(lookahead) && matches!(stack.as_slice(), [Ast(_)])) {
         let sl = stack.len();
         match stack.as_slice() {
             // match against stack as slice
-            [cdr @ .., Sym('('), e, Sym(')')] if prec(lookahead, '(') => {
+            [.., Tok(Sym(')'))] if call_start(&stack).is_some() => {
+                let (k, f) = call_start(&stack).unwrap();
+                let args = stack.split_off(k).into_iter().skip(2).filter_map(|item| match item {
+                    Ast(e) => Some(e),
+                    Tok(_) => None,
+                });
+                stack.push(Ast(Call(f, args.collect())));
+            }
+            [cdr @ .., Tok(Sym('(')), Ast(e), Tok(Sym(')'))] => {
                 stack.swap(sl - 2, sl - 3); // move e down stack
                 stack.truncate(sl - 2); // pop last two values
             }
-            [cdr @ .., e1, Sym('+'), e2] if prec(lookahead, '+') => {
+            [.., Ast(e1), Tok(t), Ast(e2)]
+                if config.binary_op(t).is_some_and(|op| {
+                    let la = config.precedence(lookahead);
+                    la < op.precedence || (la == op.precedence && op.assoc == Assoc::Left)
+                }) =>
+            {
+                let build = config.binary_op(t).map(|op| op.build).unwrap();
                 let mut tos = stack.split_off(sl - 3);
-                let b = Box::new(tos.remove(2));
-                let a = Box::new(tos.remove(0));
-                stack.push(Plus(a, b));
+                let b = Box::new(tos.remove(2).into_expr());
+                let a = Box::new(tos.remove(0).into_expr());
+                stack.push(Ast(build(a, b)));
             }
-            [cdr @ .., e1, Sym('-'), e2] if prec(lookahead, '-') => {
-                let mut tos = stack.split_off(sl - 3);
-                let b = Box::new(tos.remove(2));
-                let a = Box::new(tos.remove(0));
-                stack.push(Minus(a, b));
+            // a prefix operator, unless it follows an operand
+            [.., Tok(_), Tok(t), Ast(e1)] | [Tok(t), Ast(e1)]
+                if config
+                    .prefix_op(t)
+                    .is_some_and(|op| config.precedence(lookahead) <= op.precedence) =>
+            {
+                let build = config.prefix_op(t).map(|op| op.build).unwrap();
+                let e = build(Box::new(stack.pop().unwrap().into_expr()));
+                stack[sl - 2] = Ast(e); // e moved to stack
             }
-            [cdr @ .., e1, Sym('*'), e2] if prec(lookahead, '*') => {
-                let mut tos = stack.split_off(sl - 3);
-                let b = Box::new(tos.remove(2));
-                let a = Box::new(tos.remove(0));
-                stack.push(Times(a, b));
+            [cdr @ .., Tok(Kw("if")), Ast(c), Tok(Kw("then")), Ast(e1), Tok(Kw("else")), Ast(e2)]
+                if config.precedence(lookahead) <= LET_PRECEDENCE =>
+            {
+                let mut tos = stack.split_off(sl - 6);
+                let b = Box::new(tos.remove(5).into_expr());
+                let a = Box::new(tos.remove(3).into_expr());
+                let c = Box::new(tos.remove(1).into_expr());
+                stack.push(Ast(If(c, a, b)));
             }
-            [cdr @ .., e1, Sym('/'), e2] if prec(lookahead, '/') => {
-                let mut tos = stack.split_off(sl - 3);
-                let b = Box::new(tos.remove(2));
-                let a = Box::new(tos.remove(0));
-                stack.push(Divide(a, b));
+            [cdr @ .., Tok(Kw("let")), Ast(Var(x)), Tok(Sym('=')), Ast(e1), Tok(Kw("in")), Ast(e2)]
+                if config.precedence(lookahead) <= LET_PRECEDENCE =>
+            {
+                let x = x.clone();
+                let mut tos = stack.split_off(sl - 6);
+                let body = Box::new(tos.remove(5).into_expr());
+                let value = Box::new(tos.remove(3).into_expr());
+                stack.push(Ast(Let(x, value, body)));
             }
-            [cdr @ .., e1, Sym('%'), e2] if prec(lookahead, '%') => {
-                let mut tos = stack.split_off(sl - 3);
-                let b = Box::new(tos.remove(2));
-                let a = Box::new(tos.remove(0));
-                stack.push(Mod(a, b));
-            },
-            [cdr @ .., e2, Sym('-'), e1] if !proper(e2) && prec(lookahead, 'u') => {
-                let e = Neg(Box::new(stack.pop().unwrap()));
-                stack[sl - 2] = e; // e moved to stack
-            },
-            [Sym('-'), e1] if prec(lookahead, 'u') => {
-                let e = Neg(Box::new(stack.pop().unwrap()));
-                stack[sl - 2] = e; // e moved to stack
-            },
             _ if ti + 1 < tokens.len() => {
-                // shift
-                stack.push(lookahead.clone_token());
+                // shift, after checking that the token can go here
+                check_next(&stack, &tokens[ti], &open, config)?;
+                match lookahead {
+                    Sym('(') | Kw("let") | Kw("if") => open.push(&tokens[ti]),
+                    Sym(')') | Kw("in") | Kw("then") | Kw("else") => {
+                        // close the bodies inside the construct, then
+                        // replace it by its continuation (if any)
+                        while let Some(Kw("in") | Kw("else")) = open.last().map(|s| &s.token) {
+                            open.pop();
+                        }
+                        open.pop();
+                        if let Kw(_) = lookahead {
+                            open.push(&tokens[ti]);
+                        }
+                    }
+                    _ => {}
+                }
+                // the lexer only lets through numbers that fit N, unless
+                // the tokens were made for another type
+                let number = match lookahead {
+                    Num(x) => Some(N::from_integer(x).ok_or(Expected::Operand)),
+                    Decimal(_) if !N::DECIMALS => Some(Err(Expected::Integer)),
+                    Decimal(x) => Some(N::from_decimal(x).ok_or(Expected::Operand)),
+                    _ => None,
+                };
+                stack.push(match (number, lookahead) {
+                    (Some(Ok(v)), _) => Ast(Val(v)),
+                    (Some(Err(expected)), found) => {
+                        return Err(ParseError {
+                            offset: tokens[ti].start,
+                            end: tokens[ti].end,
+                            found: found.clone(),
+                            expected,
+                        })
+                    }
+                    (_, Ident(x)) => Ast(Var(x.clone())),
+                    (_, t) => Tok(t.clone()),
+                });
                 ti += 1;
-                lookahead = &tokens[ti];
+                lookahead = &tokens[ti].token;
             }
             _ => {
-                for ex in stack {
-                    print!("{:?}::", ex);
-                }
-                eprintln!("\nPARSING ERROR");
-                return None;
+                // stuck at end of input: missing operand, unmatched ( or
+                // a let or if that is not complete
+                check_next(&stack, &tokens[ti], &open, config)?;
+                return Err(match innermost(&open) {
+                    Some(Spanned { token: Kw(k), .. }) => ParseError {
+                        offset: tokens[ti].start,
+                        end: tokens[ti].end,
+                        found: EOF,
+                        expected: Expected::Token(closer(&Kw(k))),
+                    },
+                    Some(p) => ParseError {
+                        offset: p.start,
+                        end: p.end,
+                        found: Sym('('),
+                        expected: Expected::ClosingParen,
+                    },
+                    None => ParseError {
+                        offset: tokens[ti].start,
+                        end: tokens[ti].end,
+                        found: lookahead.clone(),
+                        expected: Expected::Operator,
+                    },
+                });
             }
         } // match
     } // while
-    return stack.pop();
-} //parse
+    Ok(stack.pop().map(Item::into_expr).unwrap_or_default())
+} //parse_spanned
+
+///////////// AM16 instruction set and code generator
+pub mod am16;
+pub mod compiler;
+pub use compiler::{compile, compile_with, Backend};
+pub mod vm16;
+pub mod assembler;
+pub mod optimize;
+pub mod peephole;
+pub mod repl;
+pub mod number;
+pub mod program;
+pub mod lambda;
+pub mod types;
+use number::{Arithmetic, Number};
 
 ///////////// bijective map
 pub mod bijectivemap;
@@ -1,34 +1,243 @@
-#![allow(dead_code)]
-use csc_7b_fc::Expr::*;
+use csc_7b_fc::am16::listing;
+use csc_7b_fc::assembler::encode_program;
+use csc_7b_fc::compiler::{compile_program, instruction_counts};
+use csc_7b_fc::optimize::simplify;
+use csc_7b_fc::peephole;
+use csc_7b_fc::program::parse_program;
+use csc_7b_fc::vm16::execute;
 use csc_7b_fc::*;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
 
-pub fn compile(e: &Expr) {
-    match e {
-        Val(x) => println!("push {}", x),
-        Neg(a) => {
-            compile(a);
-            println!("push 0");
-            println!("pop bx"); // moves 0 into bx register
-            println!("pop ax"); // load result for a into ax
-            println!("sub ax bx"); // this instruction has semantics bx -= ax
-            println!("push bx"); // always push result (0-a) on stack
-        }
-        _ => {
-            eprintln!("ERROR: COMPILATION FAILED;");
-            return;
-        }
-    } //match
-} //compile
-
-fn main() {
-    // compiles the expression given as command-line argument
-    let mut input = String::from("0");
-    std::env::args().nth(1).map(|s| {
-        input = String::from(s);
-    });
-    let tokens = lex(&input[..].trim());
-    let exp = parse(&tokens);
-    exp.map(|e| {
-        compile(&e);
-    });
+const USAGE: &str = "\
+usage: csc_7b_fc [options] [FILE]...
+Compiles the expressions of each FILE, one per line (standard input if no
+FILE or -e is given, or if FILE is -).  An expression may be preceded by
+function definitions such as `sq(x) = x*x; sq(3)`.  Blank lines and lines
+starting with # are skipped.  The code of each expression is a program
+of its own: with several expressions, each listing is preceded by a
+comment line `# FILE:LINE`, and -o FILE writes the listing or binary of
+the k-th expression to FILE.k.  Binary output to stdout takes only one
+expression.
+  --emit ast|asm|bin|run  print the syntax tree, the AM16 listing (default),
+                          the machine words as little-endian bytes, or the
+                          value computed by the VM
+  --stack, --registers    backend of the compiler (default --stack)
+  -O                      simplify the expressions before compiling them,
+                          and the compiled code with a peephole optimizer
+  --counts                print instruction counts of both backends to stderr,
+                          and what -O removed
+  -e EXPR                 compile EXPR (may be repeated)
+  -o FILE                 write the output to FILE instead of stdout
+Exit status: 0 on success, 1 if an expression could not be parsed, compiled
+or run, 2 on a usage or I/O error.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    Ast,
+    Asm,
+    Bin,
+    Run,
+}
+
+// where expressions come from: a file name ("-" for stdin) or -e
+enum Source {
+    File(String),
+    Expr(String),
+}
+
+struct Options {
+    emit: Emit,
+    backend: Backend,
+    optimize: bool,
+    counts: bool,
+    output: Option<String>,
+    sources: Vec<Source>,
+}
+
+fn options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut opts = Options {
+        emit: Emit::Asm,
+        backend: Backend::Stack,
+        optimize: false,
+        counts: false,
+        output: None,
+        sources: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or(format!("missing value after {}", flag));
+        match arg.as_str() {
+            "--emit" => {
+                opts.emit = match value("--emit")?.as_str() {
+                    "ast" => Emit::Ast,
+                    "asm" => Emit::Asm,
+                    "bin" => Emit::Bin,
+                    "run" => Emit::Run,
+                    other => return Err(format!("unknown output mode {}", other)),
+                }
+            }
+            "--registers" => opts.backend = Backend::Registers,
+            "--stack" => opts.backend = Backend::Stack,
+            "-O" => opts.optimize = true,
+            "--counts" => opts.counts = true,
+            "-e" => opts.sources.push(Source::Expr(value("-e")?)),
+            "-o" => opts.output = Some(value("-o")?),
+            "-h" | "--help" => return Err(String::new()),
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option {}", flag))
+            }
+            _ => opts.sources.push(Source::File(arg)),
+        }
+    }
+    if opts.sources.is_empty() {
+        opts.sources.push(Source::File(String::from("-")));
+    }
+    Ok(opts)
+} //options
+
+// Output for one expression, or the column of the error (if known) and
+// the diagnostic.
+fn translate(line: &str, opts: &Options) -> Result<Vec<u8>, (Option<usize>, String)> {
+    let mut p = parse_program(line).map_err(|err| (Some(err.span().0 + 1), err.to_string()))?;
+    if opts.optimize {
+        // folded with the 16-bit arithmetic of the machine
+        let mut removed = simplify(&mut p.main, ArithMode::Am16);
+        for f in &mut p.functions {
+            removed += simplify(&mut f.body, ArithMode::Am16);
+        }
+        if opts.counts {
+            eprintln!("# -O removed {} nodes", removed);
+        }
+    }
+    if opts.emit == Emit::Ast {
+        return Ok(if p.functions.is_empty() {
+            format!("{:?}\n", p.main).into_bytes()
+        } else {
+            format!("{:?}\n", p).into_bytes()
+        });
+    }
+    let mut code = compile_program(&p, opts.backend)
+        .map_err(|err| (None, format!("compilation failed; {}", err)))?;
+    if opts.optimize {
+        let shorter = peephole::optimize(&code);
+        if opts.counts {
+            eprintln!("# -O removed {} instructions", code.len() - shorter.len());
+        }
+        code = shorter;
+    }
+    if opts.counts && p.functions.is_empty() {
+        if let Ok((stack, registers)) = instruction_counts(&p.main) {
+            eprintln!(
+                "# {} instructions with the stack backend, {} with registers",
+                stack, registers
+            );
+        }
+    }
+    Ok(match opts.emit {
+        Emit::Bin => {
+            let words = encode_program(&code).map_err(|err| (None, err.to_string()))?;
+            words.iter().flat_map(|w| w.to_le_bytes()).collect()
+        }
+        Emit::Run => {
+            let vm = execute(&code).map_err(|err| (None, format!("runtime error: {}", err)))?;
+            match vm.tos() {
+                Some(v) => format!("{}\n", v).into_bytes(),
+                None => Vec::new(),
+            }
+        }
+        _ => listing(&code).into_bytes(),
+    })
+} //translate
+
+// the name of a source for diagnostics and its text
+fn read(source: &Source) -> io::Result<(String, String)> {
+    match source {
+        Source::Expr(e) => Ok((String::from("-e"), e.clone())),
+        Source::File(name) if name == "-" => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            Ok((String::from("<stdin>"), text))
+        }
+        Source::File(name) => Ok((name.clone(), std::fs::read_to_string(name)?)),
+    }
+} //read
+
+fn main() -> ExitCode {
+    let opts = match options(std::env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("ERROR: {}", msg);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let mut out: Vec<u8> = Vec::new();
+    let mut failed = false;
+    // the expressions of all sources, with the source name and line number
+    let mut exprs: Vec<(String, usize, String)> = Vec::new();
+    for source in &opts.sources {
+        let (name, text) = match read(source) {
+            Ok(input) => input,
+            Err(err) => {
+                if let Source::File(file) = source {
+                    eprintln!("ERROR: cannot read {}: {}", file, err);
+                }
+                return ExitCode::from(2);
+            }
+        };
+        for (i, line) in text.lines().enumerate() {
+            if !line.trim().is_empty() && !line.trim_start().starts_with('#') {
+                exprs.push((name.clone(), i + 1, line.to_owned()));
+            }
+        }
+    }
+    // separate programs, which cannot simply be concatenated
+    let several = exprs.len() > 1 && matches!(opts.emit, Emit::Asm | Emit::Bin);
+    // what goes to each output file, None for stdout
+    let mut files: Vec<(Option<String>, Vec<u8>)> = Vec::new();
+    for (k, (name, n, line)) in exprs.iter().enumerate() {
+        if several && k > 0 && opts.emit == Emit::Bin && opts.output.is_none() {
+            failed = true;
+            eprintln!("{}:{}: error: only one binary can go to stdout, use -o", name, n);
+            continue;
+        }
+        match translate(line, &opts) {
+            Ok(bytes) => match &opts.output {
+                Some(file) if several => files.push((Some(format!("{}.{}", file, k + 1)), bytes)),
+                _ => {
+                    if several && opts.emit == Emit::Asm {
+                        out.extend(format!("# {}:{}\n", name, n).into_bytes());
+                    }
+                    out.extend(bytes)
+                }
+            },
+            Err((column, msg)) => {
+                failed = true;
+                match column {
+                    Some(c) => eprintln!("{}:{}:{}: error: {}", name, n, c, msg),
+                    None => eprintln!("{}:{}: error: {}", name, n, msg),
+                }
+            }
+        }
+    }
+    if !several || opts.output.is_none() {
+        files.push((opts.output.clone(), out));
+    }
+    for (file, bytes) in files {
+        let written = match &file {
+            Some(file) => std::fs::write(file, &bytes).map_err(|err| (file.as_str(), err)),
+            None => io::stdout().write_all(&bytes).map_err(|err| ("stdout", err)),
+        };
+        if let Err((file, err)) = written {
+            eprintln!("ERROR: cannot write {}: {}", file, err);
+            return ExitCode::from(2);
+        }
+    }
+    if failed {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
 } //main
@@ -0,0 +1,339 @@
+//! Number types for the expression language.  An [Expr] can hold any type
+//! that implements [Number]: the default `i32`, `f64`, whose literals may
+//! have a decimal point as in `3.25`, and the exact fractions of
+//! [Rational], for which `/` does not truncate.  The same lexer and parser
+//! build all of them (see [crate::parse_number]), and [evaluate] computes
+//! their value with the same evaluator as [crate::eval_mode].
+//!
+//! Comparisons, `&&`, `||`, `!` and `if` treat zero as false, and evaluate
+//! to 0 or 1 as with i32.  The AM16 backend only computes with integers:
+//! [crate::compiler::compile_number] rejects any other constant.
+
+use crate::program::Program;
+use crate::Expr;
+use crate::{eval_scoped, ArithMode, Env, EvalError};
+use std::fmt::{Debug, Display, Formatter};
+
+type Result<T> = std::result::Result<T, EvalError>;
+
+/// Operations needed to parse and evaluate expressions over a number type.
+/// Division by zero is an [EvalError::DivideByZero] for every type.
+pub trait Number: Clone + Default + PartialEq + PartialOrd + Debug + Display {
+    /// whether literals with a decimal point, such as `3.25`, are numbers
+    /// of the type
+    const DECIMALS: bool;
+    /// the integer n
+    fn from_i32(n: i32) -> Self;
+    /// Value of an integer literal given by its digits, or None if it's
+    /// too large for the type.
+    fn from_integer(digits: &str) -> Option<Self>;
+    /// Value of a literal with a decimal point, such as `3.25`, or None if
+    /// the type cannot represent it.
+    fn from_decimal(text: &str) -> Option<Self>;
+    /// the value as an i32, if it's an integer in the range of i32
+    fn to_i32(&self) -> Option<i32>;
+    fn add(&self, other: &Self) -> Result<Self>;
+    fn sub(&self, other: &Self) -> Result<Self>;
+    fn mul(&self, other: &Self) -> Result<Self>;
+    fn div(&self, other: &Self) -> Result<Self>;
+    /// remainder of the division truncated towards zero
+    fn rem(&self, other: &Self) -> Result<Self>;
+    fn pow(&self, other: &Self) -> Result<Self>;
+    fn neg(&self) -> Result<Self> {
+        Self::from_i32(0).sub(self)
+    }
+} // trait Number
+
+/// Integers with the checked arithmetic of [ArithMode::Checked].
+impl Number for i32 {
+    const DECIMALS: bool = false;
+    fn from_i32(n: i32) -> Self {
+        n
+    }
+    fn from_integer(digits: &str) -> Option<Self> {
+        digits.parse().ok()
+    }
+    fn from_decimal(text: &str) -> Option<Self> {
+        None
+    }
+    fn to_i32(&self) -> Option<i32> {
+        Some(*self)
+    }
+    fn add(&self, other: &Self) -> Result<Self> {
+        ArithMode::Checked.binop('+', self, other)
+    }
+    fn sub(&self, other: &Self) -> Result<Self> {
+        ArithMode::Checked.binop('-', self, other)
+    }
+    fn mul(&self, other: &Self) -> Result<Self> {
+        ArithMode::Checked.binop('*', self, other)
+    }
+    fn div(&self, other: &Self) -> Result<Self> {
+        ArithMode::Checked.binop('/', self, other)
+    }
+    fn rem(&self, other: &Self) -> Result<Self> {
+        ArithMode::Checked.binop('%', self, other)
+    }
+    fn pow(&self, other: &Self) -> Result<Self> {
+        ArithMode::Checked.binop('^', self, other)
+    }
+} // impl Number for i32
+
+/// IEEE floating point: overflow gives an infinity rather than an error.
+impl Number for f64 {
+    const DECIMALS: bool = true;
+    fn from_i32(n: i32) -> Self {
+        n as f64
+    }
+    fn from_integer(digits: &str) -> Option<Self> {
+        digits.parse().ok()
+    }
+    fn from_decimal(text: &str) -> Option<Self> {
+        text.parse().ok()
+    }
+    fn to_i32(&self) -> Option<i32> {
+        let integral = self.fract() == 0.0 && *self >= i32::MIN as f64 && *self <= i32::MAX as f64;
+        integral.then_some(*self as i32)
+    }
+    fn add(&self, other: &Self) -> Result<Self> {
+        Ok(self + other)
+    }
+    fn sub(&self, other: &Self) -> Result<Self> {
+        Ok(self - other)
+    }
+    fn mul(&self, other: &Self) -> Result<Self> {
+        Ok(self * other)
+    }
+    fn div(&self, other: &Self) -> Result<Self> {
+        if *other == 0.0 {
+            return Err(EvalError::DivideByZero);
+        }
+        Ok(self / other)
+    }
+    fn rem(&self, other: &Self) -> Result<Self> {
+        if *other == 0.0 {
+            return Err(EvalError::DivideByZero);
+        }
+        Ok(self % other)
+    }
+    fn pow(&self, other: &Self) -> Result<Self> {
+        if *self == 0.0 && *other < 0.0 {
+            return Err(EvalError::DivideByZero);
+        }
+        Ok(self.powf(*other))
+    }
+    fn neg(&self) -> Result<Self> {
+        Ok(-self)
+    }
+} // impl Number for f64
+
+/// Exact fraction num/den in lowest terms, with a positive denominator.
+/// The arithmetic reports [EvalError::Overflow] when the numerator or
+/// denominator no longer fits in an i64.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i64,
+    den: i64,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+impl Rational {
+    /// The fraction num/den reduced to lowest terms, or an error if den is
+    /// zero or the result does not fit.
+    pub fn new(num: i64, den: i64) -> Result<Rational> {
+        Rational::reduce(num as i128, den as i128)
+    }
+
+    // i128 leaves room for the products of two i64 values
+    fn reduce(num: i128, den: i128) -> Result<Rational> {
+        if den == 0 {
+            return Err(EvalError::DivideByZero);
+        }
+        let g = gcd(num, den) * den.signum();
+        let fit = |n: i128| i64::try_from(n).map_err(|_| EvalError::Overflow);
+        Ok(Rational {
+            num: fit(num / g)?,
+            den: fit(den / g)?,
+        })
+    } //reduce
+
+    /// numerator, which carries the sign
+    pub fn numer(&self) -> i64 {
+        self.num
+    }
+
+    /// denominator, always positive
+    pub fn denom(&self) -> i64 {
+        self.den
+    }
+
+    // integer n as a fraction
+    fn from_i64(n: i64) -> Rational {
+        Rational { num: n, den: 1 }
+    }
+
+    // the fraction as (numerator, denominator) in i128
+    fn wide(&self) -> (i128, i128) {
+        (self.num as i128, self.den as i128)
+    }
+} // impl Rational
+
+impl Default for Rational {
+    fn default() -> Self {
+        Rational { num: 0, den: 1 }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let ((a, b), (c, d)) = (self.wide(), other.wide());
+        (a * d).cmp(&(c * b))
+    }
+}
+
+/// Prints `n` for an integer and `n/d` otherwise.
+impl Display for Rational {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+} // impl Display for Rational
+
+impl Number for Rational {
+    const DECIMALS: bool = true;
+    fn from_i32(n: i32) -> Self {
+        Rational { num: n as i64, den: 1 }
+    }
+    fn from_integer(digits: &str) -> Option<Self> {
+        digits.parse().ok().map(Rational::from_i64)
+    }
+    // 3.25 is 325/100; trailing zeros of the fraction do not count
+    fn from_decimal(text: &str) -> Option<Self> {
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        let fraction = fraction.trim_end_matches('0');
+        let digits: i128 = format!("{}{}", whole, fraction).parse().ok()?;
+        let scale = 10i128.checked_pow(fraction.len() as u32)?;
+        Rational::reduce(digits, scale).ok()
+    }
+    fn to_i32(&self) -> Option<i32> {
+        if self.den == 1 {
+            i32::try_from(self.num).ok()
+        } else {
+            None
+        }
+    }
+    fn add(&self, other: &Self) -> Result<Self> {
+        let ((a, b), (c, d)) = (self.wide(), other.wide());
+        Rational::reduce(a * d + c * b, b * d)
+    }
+    fn sub(&self, other: &Self) -> Result<Self> {
+        let ((a, b), (c, d)) = (self.wide(), other.wide());
+        Rational::reduce(a * d - c * b, b * d)
+    }
+    fn mul(&self, other: &Self) -> Result<Self> {
+        let ((a, b), (c, d)) = (self.wide(), other.wide());
+        Rational::reduce(a * c, b * d)
+    }
+    fn div(&self, other: &Self) -> Result<Self> {
+        let ((a, b), (c, d)) = (self.wide(), other.wide());
+        Rational::reduce(a * d, b * c)
+    }
+    // a - b*q where q is a/b truncated towards zero
+    fn rem(&self, other: &Self) -> Result<Self> {
+        let q = self.div(other)?;
+        let truncated = Rational::from_i64(q.num / q.den);
+        self.sub(&other.mul(&truncated)?)
+    }
+    // only integer exponents give rational results
+    fn pow(&self, other: &Self) -> Result<Self> {
+        if other.den != 1 {
+            return Err(EvalError::NotAnInteger);
+        }
+        let base = if other.num < 0 {
+            Rational::from_i64(1).div(self)?
+        } else {
+            *self
+        };
+        // square and multiply, one bit of the exponent at a time
+        let (mut p, mut square, mut n) = (Rational::from_i64(1), base, other.num.unsigned_abs());
+        while n > 0 {
+            if n & 1 == 1 {
+                p = p.mul(&square)?;
+            }
+            n >>= 1;
+            if n > 0 {
+                square = square.mul(&square)?;
+            }
+        }
+        Ok(p)
+    }
+} // impl Number for Rational
+
+/// Evaluates an expression over any [Number] type.  Variables must be
+/// bound by an enclosing `let`, and there are no functions to call.
+pub fn evaluate<N: Number>(e: &Expr<N>) -> Result<N> {
+    eval_scoped(e, &Native, &mut Env::new(), &[], 0)
+}
+
+/// Evaluates the main expression of a program over any [Number] type,
+/// with the limits of [crate::program::eval_program].
+pub fn evaluate_program<N: Number>(p: &Program<N>) -> Result<N> {
+    eval_scoped(&p.main, &Native, &mut Env::new(), &p.functions, 0)
+}
+
+// How the evaluator computes with numbers of type N: with the methods of
+// Number, or for i32 with one of the modes of ArithMode.
+pub(crate) trait Arithmetic<N> {
+    // value of a literal
+    fn literal(&self, n: &N) -> N;
+    // applies the binary operator named by the char of its token
+    fn binop(&self, op: char, a: &N, b: &N) -> Result<N>;
+    fn neg(&self, a: &N) -> Result<N>;
+    // applies the comparison named by its token
+    fn compare(&self, op: &str, a: &N, b: &N) -> bool;
+} // trait Arithmetic
+
+// the arithmetic of the Number methods, used by evaluate
+pub(crate) struct Native;
+
+impl<N: Number> Arithmetic<N> for Native {
+    fn literal(&self, n: &N) -> N {
+        n.clone()
+    }
+    fn binop(&self, op: char, a: &N, b: &N) -> Result<N> {
+        match op {
+            '+' => a.add(b),
+            '-' => a.sub(b),
+            '*' => a.mul(b),
+            '/' => a.div(b),
+            '%' => a.rem(b),
+            _ => a.pow(b),
+        }
+    }
+    fn neg(&self, a: &N) -> Result<N> {
+        a.neg()
+    }
+    fn compare(&self, op: &str, a: &N, b: &N) -> bool {
+        match op {
+            "<" => a < b,
+            "<=" => a <= b,
+            "==" => a == b,
+            _ => a != b,
+        }
+    }
+} // impl Arithmetic for Native
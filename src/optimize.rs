@@ -0,0 +1,132 @@
+//! Optimizer that rewrites an [Expr] tree before compilation: constant
+//! subtrees are folded into a single `Val`, and algebraic identities such
+//! as `x*1 = x`, `x+0 = x` and `--x = x` are used to remove nodes.  The
+//! tree is rewritten in place, so subtrees are moved and never copied.
+//!
+//! A division or modulo by zero (or `0^-1`) is never folded: it's left in
+//! the tree so that it raises its error when the program runs.  For the
+//! same reason, `x*0` only becomes `0` if x cannot fail, and the rewrites
+//! never remove an error of the arithmetic mode: `--x` is only replaced
+//! by `x` when negation wraps around, and constants that AM16 cannot hold
+//! are not folded in [ArithMode::Am16], so that they are still rejected
+//! by the compiler.
+
+use crate::compiler::constant_size;
+use crate::Expr::{self, *};
+use crate::{eval_mode, ArithMode};
+use std::mem::take;
+
+/// Simplifies an expression in place, folding constants with the
+/// arithmetic of the given mode (use [ArithMode::Am16] when compiling for
+/// AM16).  Returns the number of nodes removed from the tree.
+pub fn simplify(e: &mut Expr, mode: ArithMode) -> usize {
+    let before = size(e);
+    fold(e, mode);
+    before - size(e)
+} //simplify
+
+/// number of nodes in the tree
+pub fn size(e: &Expr) -> usize {
+    e.iter().count()
+}
+
+// true if evaluating e could raise a division by zero, which includes a
+// power with a negative exponent, call a function that fails, overflow in
+// checked mode, or hold a constant that does not fit the mode
+fn may_fail(e: &Expr, mode: ArithMode) -> bool {
+    let fails = match e {
+        Divide(..) | Mod(..) | Pow(..) | Call(..) => true,
+        Plus(..) | Minus(..) | Times(..) | Neg(..) => mode == ArithMode::Checked,
+        Val(n) => !in_range(*n, mode),
+        _ => false,
+    };
+    fails || e.children().into_iter().any(|c| may_fail(c, mode))
+} //may_fail
+
+// false for the constants outside of the 16 bits of AM16, which folding
+// would truncate instead of leaving them for the compiler to reject
+fn in_range(n: i32, mode: ArithMode) -> bool {
+    mode != ArithMode::Am16 || constant_size(n).is_some()
+}
+
+// true if all constants of e are in range, so that e can be removed
+// without hiding them from the compiler
+fn fits(e: &Expr, mode: ArithMode) -> bool {
+    e.iter().all(|n| !matches!(n, Val(v) if !in_range(*v, mode)))
+}
+
+fn is_val(e: &Expr, n: i32) -> bool {
+    matches!(e, Val(m) if *m == n)
+}
+
+// postorder rewrite: children first, then the node itself
+fn fold(e: &mut Expr, mode: ArithMode) {
+    // an if with a constant condition is replaced by one of its branches
+    // before the other one is simplified for nothing
+    if let If(c, a, b) = e {
+        fold(c, mode);
+        if let Val(v) = **c {
+            let dropped = if v != 0 { &**b } else { &**a };
+            if in_range(v, mode) && fits(dropped, mode) {
+                *e = take(if v != 0 { &mut **a } else { &mut **b });
+                fold(e, mode);
+                return;
+            }
+        }
+        fold(a, mode);
+        fold(b, mode);
+    } else {
+        for child in e.children_mut() {
+            fold(child, mode);
+        }
+    }
+    let constant = !matches!(e, Let(..) | Call(..))
+        && e.children().iter().all(|c| matches!(c, Val(n) if in_range(*n, mode)));
+    if constant && !e.children().is_empty() {
+        if let Ok(v) = eval_mode(e, mode) {
+            *e = Val(v);
+        }
+        return; // division by zero is left for run time
+    }
+    let wraps = matches!(mode, ArithMode::Wrapping | ArithMode::Am16);
+    let replacement = match e {
+        Neg(a) if wraps => match &mut **a {
+            Neg(x) => Some(take(&mut **x)),
+            _ => None,
+        },
+        Times(a, b) if is_val(b, 1) => Some(take(&mut **a)),
+        Times(a, b) if is_val(a, 1) => Some(take(&mut **b)),
+        Times(a, b)
+            if (is_val(a, 0) && !may_fail(b, mode)) || (is_val(b, 0) && !may_fail(a, mode)) =>
+        {
+            Some(Val(0))
+        }
+        Plus(a, b) | Minus(a, b) if is_val(b, 0) => Some(take(&mut **a)),
+        Plus(a, b) if is_val(a, 0) => Some(take(&mut **b)),
+        // x - c becomes x + (-c) when -c takes fewer instructions to build
+        // than c, as when c is negative and -c fits the immediate of push
+        Minus(a, b) => match **b {
+            Val(c) if c != i32::MIN && shorter(-c, c) => {
+                Some(Plus(Box::new(take(&mut **a)), Box::new(Val(-c))))
+            }
+            _ => None,
+        },
+        And(a, b) if is_val(a, 0) && fits(b, mode) => Some(Val(0)),
+        Or(a, b) if matches!(**a, Val(v) if v != 0 && in_range(v, mode)) && fits(b, mode) => {
+            Some(Val(1))
+        }
+        _ => None,
+    };
+    if let Some(r) = replacement {
+        *e = r;
+        fold(e, mode); // the new node may be simplified further
+    }
+} //fold
+
+// true if the constant m takes fewer AM16 instructions than n
+fn shorter(m: i32, n: i32) -> bool {
+    match (constant_size(m), constant_size(n)) {
+        (Some(a), Some(b)) => a < b,
+        _ => false,
+    }
+} //shorter
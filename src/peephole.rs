@@ -0,0 +1,165 @@
+//! Peephole optimizer for compiled AM16 programs.  The push/pop protocol
+//! of the compiler often moves a value through the stack only to pop it
+//! right away.  This pass looks at adjacent pairs of instructions and
+//! rewrites
+//!
+//!   * `push r; pop r` to nothing, unless r is sp,
+//!   * `push r1; pop r2` to `mov r1 r2`,
+//!   * `push n; pop r` to `mov n r` when n fits the 8-bit immediate of mov,
+//!
+//! repeating until no pattern is left.  A pair is never merged when the
+//! second instruction is the target of a branch, and branch targets are
+//! renumbered after instructions are removed.
+//!
+//! The registers and the stack are the same at the end of the optimized
+//! program, which can be checked with [verify].  The only difference is
+//! that the dead word above the top of the stack is not written.
+
+use crate::am16::Instr::{self, *};
+use crate::am16::Operand::{Imm, Reg as R};
+use crate::am16::Reg::Sp;
+use crate::compiler::ORIGIN;
+use crate::vm16::{execute, Registers, VmError};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+/// Applies the peephole rewrites until the program no longer changes.
+pub fn optimize(code: &[Instr]) -> Vec<Instr> {
+    let mut code = code.to_vec();
+    while let Some(shorter) = pass(&code) {
+        code = shorter;
+    }
+    code
+} //optimize
+
+fn target(inst: &Instr) -> Option<u16> {
+    match inst {
+        Jmp(t) | Jnz(t) | Jz(t) | Jn(t) | Call(t) => Some(*t),
+        _ => None,
+    }
+}
+
+// the pair of instructions as a single one, or None for an empty sequence
+fn combine(first: Instr, second: Instr) -> Option<Option<Instr>> {
+    match (first, second) {
+        // pop sp is an error of the machine, which must be kept
+        (Push(R(r)), Pop(d)) if r == d && r != Sp => Some(None),
+        (Push(R(r)), Pop(d)) if r != Sp && d != Sp => Some(Some(Mov(R(r), d))),
+        (Push(Imm(n)), Pop(d)) if (0..=255).contains(&n) && d != Sp => Some(Some(Mov(Imm(n), d))),
+        _ => None,
+    }
+} //combine
+
+// One left-to-right pass over the program, or None if nothing changed.
+fn pass(code: &[Instr]) -> Option<Vec<Instr>> {
+    let targets: HashSet<u16> = code.iter().filter_map(target).collect();
+    let mut out = Vec::with_capacity(code.len());
+    // new index of each old instruction, and of the end of the program
+    let mut moved = vec![0; code.len() + 1];
+    let mut i = 0;
+    while i < code.len() {
+        moved[i] = out.len();
+        let pair = code
+            .get(i + 1)
+            .filter(|_| !targets.contains(&(i as u16 + 1 + ORIGIN)));
+        match pair.and_then(|next| combine(code[i], *next)) {
+            Some(inst) => {
+                moved[i + 1] = out.len();
+                out.extend(inst);
+                i += 2;
+            }
+            None => {
+                out.push(code[i]);
+                i += 1;
+            }
+        }
+    }
+    moved[code.len()] = out.len();
+    if out == code {
+        return None;
+    }
+    // renumber branches, leaving targets outside of the program alone
+    let relocate = |t: u16| match (t as usize).checked_sub(ORIGIN as usize) {
+        Some(k) if k <= code.len() => moved[k] as u16 + ORIGIN,
+        _ => t,
+    };
+    for inst in out.iter_mut() {
+        *inst = match *inst {
+            Jmp(t) => Jmp(relocate(t)),
+            Jnz(t) => Jnz(relocate(t)),
+            Jz(t) => Jz(relocate(t)),
+            Jn(t) => Jn(relocate(t)),
+            Call(t) => Call(relocate(t)),
+            other => other,
+        };
+    }
+    Some(out)
+} //pass
+
+/// Final state of a program: the registers and the stack.  The pc and ir
+/// registers are left at 0 since they depend on the length of the code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub regs: Registers,
+    pub stack: Vec<i16>,
+}
+
+/// Runs a program on a fresh [crate::vm16::Vm] and returns its final state.
+pub fn outcome(code: &[Instr]) -> Result<Outcome, VmError> {
+    let vm = execute(code)?;
+    Ok(Outcome {
+        regs: Registers {
+            pc: 0,
+            ir: 0,
+            ..vm.regs
+        },
+        stack: vm.stack().to_vec(),
+    })
+} //outcome
+
+/// Outcomes of an original and an optimized program that do not agree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub original: Result<Outcome, VmError>,
+    pub optimized: Result<Outcome, VmError>,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let r = &self.regs;
+        write!(
+            f,
+            "ax={}, bx={}, cx={}, sp={}, bp={}, ma={}, stack={:?}",
+            r.ax, r.bx, r.cx, r.sp as u16, r.bp as u16, r.ma as u16, self.stack
+        )
+    }
+} // impl Display for Outcome
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let show = |r: &Result<Outcome, VmError>| match r {
+            Ok(o) => o.to_string(),
+            Err(e) => format!("error: {}", e),
+        };
+        write!(
+            f,
+            "original: {}\noptimized: {}",
+            show(&self.original),
+            show(&self.optimized)
+        )
+    }
+} // impl Display for Mismatch
+
+/// Runs both programs and compares their registers and stacks.  Two runs
+/// that fail with the same error, such as a division by zero, agree.
+pub fn verify(original: &[Instr], optimized: &[Instr]) -> Result<(), Mismatch> {
+    let (a, b) = (outcome(original), outcome(optimized));
+    if a == b {
+        Ok(())
+    } else {
+        Err(Mismatch {
+            original: a,
+            optimized: b,
+        })
+    }
+} //verify
@@ -0,0 +1,155 @@
+//! Programs made of top-level function definitions and a main expression,
+//! such as
+//!
+//! ```text
+//! fact(n) = if n == 0 then 1 else n * fact(n-1);
+//! fact(5)
+//! ```
+//!
+//! Each definition `name(x, y) = body` ends with `;`, and the expression
+//! after the last definition is the value of the program.  Functions may
+//! call each other, or themselves, in any order, and a later definition
+//! with the same name replaces an earlier one.  The body of a function
+//! only sees its own parameters, not the variables of the caller.
+//!
+//! [eval_program] interprets a program, while
+//! [crate::compiler::compile_program] translates it to AM16 code that
+//! uses `call` and `ret` with a stack frame for each call.  Programs over
+//! other number types are parsed by [parse_number_program] and run by
+//! [crate::number::evaluate_program].
+
+use crate::number::Number;
+use crate::Expr::{self, *};
+use crate::{
+    eval_scoped, parse_spanned_with, tokenize_with, ArithMode, Env, EvalError, Expected,
+    ParseError, ParserConfig, Spanned, SyntaxError, Token::*,
+};
+use std::fmt::{Display, Formatter};
+
+/// How deeply calls can be nested in [eval_program] before it gives up
+/// with [EvalError::RecursionLimit].  The interpreter recurses on the
+/// calls as well as on the expressions, and this many calls of a small
+/// function take about 1 MiB of stack in an unoptimized build.
+pub const MAX_CALL_DEPTH: usize = 100;
+
+/// A function definition `name(params) = body`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function<N = i32> {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Expr<N>,
+}
+
+/// Function definitions followed by the expression that calls them, over
+/// i32 unless another [Number] type is given
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program<N = i32> {
+    pub functions: Vec<Function<N>>,
+    pub main: Expr<N>,
+}
+
+/// Prints the program in the syntax accepted by [parse_program], one
+/// definition per line.
+impl<N: Number> Display for Program<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for def in &self.functions {
+            writeln!(f, "{}({}) = {};", def.name, def.params.join(", "), def.body)?;
+        }
+        write!(f, "{}", self.main)
+    }
+} // impl Display for Program
+
+// the definition of f called with n arguments
+pub(crate) fn lookup<'a, N>(
+    defs: &'a [Function<N>],
+    f: &str,
+    n: usize,
+) -> Result<&'a Function<N>, EvalError> {
+    match defs.iter().rev().find(|def| def.name == f) {
+        Some(def) if def.params.len() == n => Ok(def),
+        Some(def) => Err(EvalError::WrongArity(f.to_owned(), def.params.len())),
+        None => Err(EvalError::UnknownFunction(f.to_owned())),
+    }
+} //lookup
+
+/// Evaluates the main expression of a program with the given arithmetic
+/// mode.  Calls can be nested [MAX_CALL_DEPTH] deep, on the stack of the
+/// caller: the 2 MiB of a spawned thread hold that many calls of functions
+/// whose bodies nest a few operators deep, and programs with larger
+/// bodies need a thread with a larger stack.
+pub fn eval_program(p: &Program, mode: ArithMode) -> Result<i32, EvalError> {
+    eval_scoped(&p.main, &mode, &mut Env::new(), &p.functions, 0)
+} //eval_program
+
+/// Tokenizes and parses a program.  The definitions are separated by `;`
+/// at the top level, so the tokens of each body are parsed on their own
+/// with [crate::parse_spanned].
+pub fn parse_program(inp: &str) -> Result<Program, SyntaxError> {
+    parse_number_program(inp)
+}
+
+/// Parses a program over another type of numbers, as [crate::parse_number]
+/// does for expressions.  It can be run by [crate::number::evaluate_program].
+pub fn parse_number_program<N: Number>(inp: &str) -> Result<Program<N>, SyntaxError> {
+    let config = ParserConfig::default();
+    let tokens = tokenize_with(inp, &config)?;
+    let mut functions = Vec::new();
+    let mut rest = &tokens[..];
+    while let Some(semi) = rest.iter().position(|s| s.token == Sym(';')) {
+        functions.push(definition(&rest[..semi], &rest[semi], &config)?);
+        rest = &rest[semi + 1..];
+    }
+    let main = parse_spanned_with(rest, &config)?;
+    Ok(Program { functions, main })
+} //parse_number_program
+
+// Parses `name(x, y) = body` from the tokens before the `;` that ends it.
+// The body is parsed as if the input ended at the `;`.
+fn definition<N: Number>(
+    tokens: &[Spanned],
+    semi: &Spanned,
+    config: &ParserConfig<N>,
+) -> Result<Function<N>, ParseError> {
+    // the token at i, or the `;` past the end
+    let at = |i: usize| tokens.get(i).unwrap_or(semi);
+    let error = |i: usize, expected: Expected| ParseError {
+        offset: at(i).start,
+        end: at(i).end,
+        found: at(i).token.clone(),
+        expected,
+    };
+    let name = match &at(0).token {
+        Ident(f) => f.clone(),
+        _ => return Err(error(0, Expected::Identifier)),
+    };
+    if at(1).token != Sym('(') {
+        return Err(error(1, Expected::Token("(")));
+    }
+    let mut params = Vec::new();
+    let mut i = 2;
+    if at(i).token == Sym(')') {
+        i += 1;
+    } else {
+        loop {
+            match &at(i).token {
+                Ident(x) => params.push(x.clone()),
+                _ => return Err(error(i, Expected::Identifier)),
+            }
+            i += 1;
+            match at(i).token {
+                Sym(',') => i += 1,
+                Sym(')') => {
+                    i += 1;
+                    break;
+                }
+                _ => return Err(error(i, Expected::Token(")"))),
+            }
+        }
+    }
+    if at(i).token != Sym('=') {
+        return Err(error(i, Expected::Token("=")));
+    }
+    let mut body = tokens[i + 1..].to_vec();
+    body.push(Spanned { token: EOF, start: semi.start, end: semi.end });
+    Ok(Function { name, params, body: parse_spanned_with(&body, config)? })
+} //definition
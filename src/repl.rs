@@ -0,0 +1,211 @@
+//! Read-eval-print loop behind the `repl` binary.  Each line is either an
+//! expression, which is evaluated, or one of the commands
+//!
+//!   * `:ast e` prints the abstract syntax tree of e,
+//!   * `:asm e` prints the AM16 code compiled from e,
+//!   * `:run e` runs the compiled code on the VM, tracing the registers
+//!     after each instruction up to the end or a runtime error,
+//!   * `:let x = e` binds x to the value of e for the following lines,
+//!   * `:history` lists the lines entered so far,
+//!   * `:help` lists the commands,
+//!   * `:quit` or `:q` ends the session.
+//!
+//! Variables bound with `:let` are known to all of the commands: for
+//! `:asm` and `:run` the expression is wrapped in `let` expressions that
+//! bind the ones it uses, since compiled code has no other way to see
+//! them.  An error is reported for the line that caused it and the
+//! session goes on.
+
+use crate::am16::listing;
+use crate::compiler::{CompileError, ORIGIN};
+use crate::vm16::{Vm, VmError};
+use crate::{compile, eval_with, lex, parse_str, Env, EvalError, Expr, SyntaxError, Token};
+use std::fmt::{Display, Formatter};
+
+/// number of instructions traced by `:run` before giving up
+pub const TRACE_LIMIT: usize = 10_000;
+
+/// Text printed by `:help`
+pub const HELP: &str = "\
+expr          evaluate an expression
+:ast expr     show the abstract syntax tree
+:asm expr     show the compiled AM16 code
+:run expr     run the compiled code on the VM with a register trace
+:let x = expr bind x for the following lines
+:history      list the lines entered so far
+:help         show this message
+:quit         leave (as does end of input)";
+
+/// Errors reported for a line of input
+#[derive(Debug)]
+pub enum ReplError {
+    Syntax(SyntaxError),
+    Eval(EvalError),
+    Compile(CompileError),
+    Vm(VmError),
+    /// a line starting with `:` that is not a known command
+    UnknownCommand(String),
+    /// a `:let` that does not have the form `:let x = e`
+    BadBinding,
+}
+
+impl Display for ReplError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplError::Syntax(e) => write!(f, "{}", e),
+            ReplError::Eval(e) => write!(f, "evaluation error: {}", e),
+            ReplError::Compile(e) => write!(f, "compilation error: {}", e),
+            ReplError::Vm(e) => write!(f, "VM error: {}", e),
+            ReplError::UnknownCommand(c) => write!(f, "unknown command {}, try :help", c),
+            ReplError::BadBinding => write!(f, "expected :let x = expr"),
+        }
+    }
+} // impl Display for ReplError
+
+impl std::error::Error for ReplError {}
+
+impl From<SyntaxError> for ReplError {
+    fn from(e: SyntaxError) -> Self {
+        ReplError::Syntax(e)
+    }
+}
+
+impl From<EvalError> for ReplError {
+    fn from(e: EvalError) -> Self {
+        ReplError::Eval(e)
+    }
+}
+
+impl From<CompileError> for ReplError {
+    fn from(e: CompileError) -> Self {
+        ReplError::Compile(e)
+    }
+}
+
+impl From<VmError> for ReplError {
+    fn from(e: VmError) -> Self {
+        ReplError::Vm(e)
+    }
+}
+
+/// State of a REPL session: the `:let` bindings and the history of lines.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    bindings: Vec<(String, i32)>,
+    history: Vec<String>,
+    finished: bool,
+}
+
+impl Session {
+    /// creates a session without bindings or history
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    /// lines entered so far, oldest first
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// whether `:quit` has ended the session
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Handles one line of input, returning the text to print.  Blank
+    /// lines give empty output and are not recorded in the history.
+    pub fn line(&mut self, input: &str) -> Result<String, ReplError> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(String::new());
+        }
+        self.history.push(input.to_owned());
+        let (command, rest) = match input.strip_prefix(':') {
+            Some(cmd) => cmd.split_once(char::is_whitespace).unwrap_or((cmd, "")),
+            None => ("", input),
+        };
+        match command {
+            "" => Ok(self.eval(rest)?.to_string()),
+            "ast" => Ok(format!("{:?}", parse_str(rest)?)),
+            "asm" => Ok(listing(&compile(&self.closed(rest)?)?).trim_end().to_owned()),
+            "run" => self.trace(rest),
+            "let" => {
+                let (x, e) = rest.split_once('=').ok_or(ReplError::BadBinding)?;
+                let x = match lex(x.trim()).as_slice() {
+                    [Token::Ident(x), Token::EOF] => x.clone(),
+                    _ => return Err(ReplError::BadBinding),
+                };
+                let v = self.eval(e)?;
+                self.bindings.push((x.clone(), v));
+                Ok(format!("{} = {}", x, v))
+            }
+            "history" => {
+                let lines = self.history.iter().enumerate();
+                let numbered: Vec<String> =
+                    lines.map(|(i, l)| format!("{:4}  {}", i + 1, l)).collect();
+                Ok(numbered.join("\n"))
+            }
+            "help" => Ok(HELP.to_owned()),
+            "quit" | "q" => {
+                self.finished = true;
+                Ok(String::new())
+            }
+            _ => Err(ReplError::UnknownCommand(format!(":{}", command))),
+        }
+    } //line
+
+    // value of an expression with the current bindings
+    fn eval(&self, input: &str) -> Result<i32, ReplError> {
+        let mut env = Env::new();
+        for (x, v) in &self.bindings {
+            env.bind(x, *v);
+        }
+        Ok(eval_with(&parse_str(input)?, &env)?)
+    } //eval
+
+    // the expression inside lets that bind the variables of the session
+    // it uses, to their most recent values
+    fn closed(&self, input: &str) -> Result<Expr, ReplError> {
+        let mut e = parse_str(input)?;
+        let used: Vec<String> = e
+            .iter()
+            .filter_map(|n| match n {
+                Expr::Var(x) => Some(x.clone()),
+                _ => None,
+            })
+            .collect();
+        for (i, (x, v)) in self.bindings.iter().enumerate().rev() {
+            let latest = !self.bindings[i + 1..].iter().any(|(y, _)| y == x);
+            if latest && used.contains(x) {
+                e = Expr::Let(x.clone(), Box::new(Expr::Val(*v)), Box::new(e));
+            }
+        }
+        Ok(e)
+    } //closed
+
+    // Runs the compiled expression one instruction at a time, printing
+    // each instruction with the state of the machine after it.  A runtime
+    // error ends the trace, which is still shown up to that point.
+    fn trace(&self, input: &str) -> Result<String, ReplError> {
+        let code = compile(&self.closed(input)?)?;
+        let mut vm = Vm::new();
+        vm.load(&code)?;
+        let mut out = Vec::new();
+        while !vm.halted() {
+            if vm.steps() >= TRACE_LIMIT {
+                out.push(ReplError::Vm(VmError::StepLimit(TRACE_LIMIT)).to_string());
+                return Ok(out.join("\n"));
+            }
+            let inst = code[(vm.regs.pc - ORIGIN) as usize];
+            if let Err(e) = vm.step() {
+                out.push(format!("{:16} {}", inst.to_string(), ReplError::Vm(e)));
+                return Ok(out.join("\n"));
+            }
+            out.push(format!("{:16} {}", inst.to_string(), vm));
+        }
+        if let Some(v) = vm.tos() {
+            out.push(format!("result: {}", v));
+        }
+        Ok(out.join("\n"))
+    } //trace
+} // impl Session
@@ -0,0 +1,338 @@
+//! Hindley-Milner type inference for the lambda terms of [crate::lambda],
+//! giving the types that F# infers for `fsharp/lambdacalc.fsx`.  Pure
+//! lambda terms only have type variables and function types, printed
+//! like F# does as in `('a -> 'b) -> 'a -> 'b`.
+//!
+//! A `let` generalizes the type of its definition, so that the defined
+//! name can be used at different types in the body: `let i = \x.x in i i`
+//! has a type while `(\i.i i) (\x.x)` does not.  The definitions of a
+//! [TypeEnv], like the top-level `let`s of F#, are generalized the same way.
+//! A term without a type fails to unify two types, which are both
+//! reported; without type constants, this always means that a type would
+//! have to contain itself, as for `\x.x x`.
+
+use crate::lambda::{parse_term, LambdaError, Term, PRELUDE};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+/// Types of lambda terms.  Variables are numbered.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Var(usize),
+    Arrow(Box<Type>, Box<Type>), // a -> b
+}
+use Type::*;
+
+/// A type with quantified variables, such as the type `'a -> 'a` of I,
+/// which can be used at any type `t -> t`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scheme {
+    pub vars: Vec<usize>,
+    pub ty: Type,
+}
+
+/// Reasons why a term has no type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeError {
+    /// the two types that could not be unified
+    Mismatch(Type, Type),
+    /// variable without a definition
+    Unbound(String),
+    /// error in the source of a definition
+    Syntax(LambdaError),
+}
+
+impl Type {
+    /// the function type `a -> b`
+    pub fn arrow(a: Type, b: Type) -> Type {
+        Arrow(Box::new(a), Box::new(b))
+    }
+
+    // adds the variables of the type, in order of appearance
+    fn collect_vars(&self, vars: &mut Vec<usize>) {
+        match self {
+            Var(v) if !vars.contains(v) => vars.push(*v),
+            Var(_) => {}
+            Arrow(a, b) => {
+                a.collect_vars(vars);
+                b.collect_vars(vars);
+            }
+        }
+    } //collect_vars
+
+    // Prints the type, the variable numbered vars[i] as the i-th name.
+    // The left side of an arrow needs parentheses if it's an arrow.
+    fn fmt_with(&self, vars: &[usize], left: bool, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Var(v) => {
+                let i = vars.iter().position(|w| w == v).unwrap_or(vars.len());
+                write!(f, "{}", var_name(i))
+            }
+            Arrow(a, b) => {
+                if left {
+                    write!(f, "(")?;
+                }
+                a.fmt_with(vars, true, f)?;
+                write!(f, " -> ")?;
+                b.fmt_with(vars, false, f)?;
+                if left {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    } //fmt_with
+} // impl Type
+
+// 'a to 'z, then 'a1 to 'z1 and so on
+fn var_name(i: usize) -> String {
+    let letter = (b'a' + (i % 26) as u8) as char;
+    match i / 26 {
+        0 => format!("'{}", letter),
+        n => format!("'{}{}", letter, n),
+    }
+} //var_name
+
+/// Prints the type with its variables named `'a`, `'b`, ... in order of
+/// appearance, so that types equal up to renaming print the same.
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut vars = Vec::new();
+        self.collect_vars(&mut vars);
+        self.fmt_with(&vars, false, f)
+    }
+}
+
+/// Prints the two types with the same names for the same variables.
+impl Display for TypeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeError::Mismatch(a, b) => {
+                let mut vars = Vec::new();
+                a.collect_vars(&mut vars);
+                b.collect_vars(&mut vars);
+                write!(f, "cannot unify ")?;
+                a.fmt_with(&vars, false, f)?;
+                write!(f, " with ")?;
+                b.fmt_with(&vars, false, f)
+            }
+            TypeError::Unbound(x) => write!(f, "unbound variable {}", x),
+            TypeError::Syntax(e) => write!(f, "{}", e),
+        }
+    }
+} // impl Display for TypeError
+
+impl std::error::Error for TypeError {}
+
+impl From<LambdaError> for TypeError {
+    fn from(e: LambdaError) -> Self {
+        TypeError::Syntax(e)
+    }
+}
+
+// State of the inference: the type bound to each type variable so far,
+// None for the ones that are still free.
+#[derive(Default)]
+struct Infer {
+    bindings: Vec<Option<Type>>,
+}
+
+impl Infer {
+    fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Var(self.bindings.len() - 1)
+    }
+
+    // the type with its bound variables replaced, at the top only
+    fn shallow(&self, t: &Type) -> Type {
+        let mut t = t.clone();
+        while let Var(v) = t {
+            match &self.bindings[v] {
+                Some(bound) => t = bound.clone(),
+                None => break,
+            }
+        }
+        t
+    } //shallow
+
+    // the type with all of its bound variables replaced
+    fn resolve(&self, t: &Type) -> Type {
+        match self.shallow(t) {
+            Arrow(a, b) => Type::arrow(self.resolve(&a), self.resolve(&b)),
+            v => v,
+        }
+    }
+
+    fn occurs(&self, v: usize, t: &Type) -> bool {
+        match self.shallow(t) {
+            Var(w) => v == w,
+            Arrow(a, b) => self.occurs(v, &a) || self.occurs(v, &b),
+        }
+    }
+
+    // Makes the types equal by binding variables.  A variable cannot be
+    // bound to a type that contains it, which would be infinite.
+    fn unify(&mut self, a: &Type, b: &Type) -> Result<(), TypeError> {
+        match (self.shallow(a), self.shallow(b)) {
+            (Var(v), Var(w)) if v == w => Ok(()),
+            (Var(v), t) | (t, Var(v)) => {
+                if self.occurs(v, &t) {
+                    return Err(TypeError::Mismatch(Var(v), self.resolve(&t)));
+                }
+                self.bindings[v] = Some(t);
+                Ok(())
+            }
+            (Arrow(a1, b1), Arrow(a2, b2)) => {
+                self.unify(&a1, &a2)?;
+                self.unify(&b1, &b2)
+            }
+        }
+    } //unify
+
+    // the type of a scheme with fresh variables for the quantified ones
+    fn instantiate(&mut self, s: &Scheme) -> Type {
+        let fresh: Vec<(usize, Type)> = s.vars.iter().map(|v| (*v, self.fresh())).collect();
+        replace(&s.ty, &fresh)
+    }
+
+    // Quantifies the variables of t that are not free in the environment.
+    // Only the free variables of a scheme are resolved: the quantified ones
+    // of the definitions of a TypeEnv were numbered by another Infer.
+    fn generalize(&self, t: &Type, env: &[(String, Scheme)]) -> Scheme {
+        let ty = self.resolve(t);
+        let mut in_env = BTreeSet::new();
+        for (_, s) in env {
+            let mut free = Vec::new();
+            s.ty.collect_vars(&mut free);
+            for v in free.into_iter().filter(|v| !s.vars.contains(v)) {
+                let mut vars = Vec::new();
+                self.resolve(&Var(v)).collect_vars(&mut vars);
+                in_env.extend(vars);
+            }
+        }
+        let mut vars = Vec::new();
+        ty.collect_vars(&mut vars);
+        vars.retain(|v| !in_env.contains(v));
+        Scheme { vars, ty }
+    } //generalize
+
+    // Algorithm W, with the variables in scope innermost last
+    fn infer(&mut self, t: &Term, env: &mut Vec<(String, Scheme)>) -> Result<Type, TypeError> {
+        match t {
+            Term::Var(x) => match env.iter().rev().find(|(y, _)| y == x) {
+                Some((_, s)) => {
+                    let s = s.clone();
+                    Ok(self.instantiate(&s))
+                }
+                None => Err(TypeError::Unbound(x.clone())),
+            },
+            Term::Lam(x, body) => {
+                let a = self.fresh();
+                env.push((x.clone(), Scheme { vars: Vec::new(), ty: a.clone() }));
+                let b = self.infer(body, env);
+                env.pop();
+                Ok(Type::arrow(a, b?))
+            }
+            Term::App(f, a) => {
+                let tf = self.infer(f, env)?;
+                let ta = self.infer(a, env)?;
+                let r = self.fresh();
+                self.unify(&tf, &Type::arrow(ta, r.clone()))?;
+                Ok(r)
+            }
+            Term::Let(x, a, body) => {
+                let ta = self.infer(a, env)?;
+                let s = self.generalize(&ta, env);
+                env.push((x.clone(), s));
+                let b = self.infer(body, env);
+                env.pop();
+                b
+            }
+        }
+    } //infer
+} // impl Infer
+
+// t with the variables of the pairs replaced by their types
+fn replace(t: &Type, pairs: &[(usize, Type)]) -> Type {
+    match t {
+        Var(v) => pairs.iter().find(|(w, _)| w == v).map_or(t.clone(), |(_, u)| u.clone()),
+        Arrow(a, b) => Type::arrow(replace(a, pairs), replace(b, pairs)),
+    }
+}
+
+/// Infers the most general type of a closed term.
+pub fn infer(t: &Term) -> Result<Type, TypeError> {
+    TypeEnv::new().infer(t)
+}
+
+/// Named definitions with their generalized types, which later terms can
+/// use at any instance of those types.
+#[derive(Debug, Clone, Default)]
+pub struct TypeEnv {
+    defs: Vec<(String, Scheme)>,
+}
+
+impl TypeEnv {
+    /// an environment without definitions
+    pub fn new() -> Self {
+        TypeEnv::default()
+    }
+
+    /// The definitions of [PRELUDE] with their types.  FIX, the Y
+    /// combinator, has no type: as the F# script does, it's added as a
+    /// constant of type `('a -> 'a) -> 'a` instead.
+    pub fn standard() -> Self {
+        let mut env = TypeEnv::new();
+        for (name, src) in PRELUDE {
+            if *name == "FIX" {
+                let a = Var(0);
+                let ty = Type::arrow(Type::arrow(a.clone(), a.clone()), a);
+                env.assume(name, Scheme { vars: vec![0], ty });
+            } else {
+                env.define(name, src).expect("the prelude has types");
+            }
+        }
+        env
+    } //standard
+
+    /// Adds a name of the given type, without a definition.
+    pub fn assume(&mut self, name: &str, s: Scheme) {
+        self.defs.retain(|(x, _)| x != name);
+        self.defs.push((name.to_owned(), s));
+    }
+
+    /// Parses the source of a definition and adds it with its generalized
+    /// type, which is returned.
+    pub fn define(&mut self, name: &str, src: &str) -> Result<Type, TypeError> {
+        let t = parse_term(src)?;
+        let mut inf = Infer::default();
+        let ty = inf.infer(&t, &mut self.defs.clone())?;
+        let s = inf.generalize(&ty, &[]);
+        let ty = s.ty.clone();
+        self.assume(name, s);
+        Ok(ty)
+    } //define
+
+    /// the type scheme of a name
+    pub fn get(&self, name: &str) -> Option<&Scheme> {
+        self.defs.iter().find(|(x, _)| x == name).map(|(_, s)| s)
+    }
+
+    /// infers the type of a term that may use the defined names
+    pub fn infer(&self, t: &Term) -> Result<Type, TypeError> {
+        let mut inf = Infer::default();
+        let ty = inf.infer(t, &mut self.defs.clone())?;
+        Ok(inf.resolve(&ty))
+    }
+} // impl TypeEnv
+
+/// Lists the definitions with their types, one per line, as F# does when
+/// loading a script: `val I : 'a -> 'a`.
+impl Display for TypeEnv {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (x, s) in &self.defs {
+            writeln!(f, "val {} : {}", x, s.ty)?;
+        }
+        Ok(())
+    }
+} // impl Display for TypeEnv
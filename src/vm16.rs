@@ -0,0 +1,359 @@
+//! Native Rust implementation of Abstract Machine AM16, equivalent to the
+//! C++ virtual machine in `vm16/vmfplusone.cpp`.  The memory consists of
+//! 32K 16-bit words divided into the following segments:
+//!
+//!   * Code Segment: address 0-4095 (programs are loaded starting at 1)
+//!   * Stack Segment: address 4096-12287
+//!   * Heap Segment: from the top of memory (32767) down to 12288
+//!
+//! All arithmetic is on 16-bit two's complement integers (i16), which
+//! wrap around on overflow just like the `int16_t` registers of the C++
+//! version.  Instead of crashing, the Rust machine reports errors such as
+//! stack underflow or division by zero with a [VmError].
+
+use crate::am16::{EncodeError, Instr, Operand, Reg};
+use std::fmt::{Display, Formatter};
+
+/// number of 16-bit words of memory (64K bytes)
+pub const MEMSIZE: usize = 65536 / 2;
+/// address of the first word of the code segment
+pub const CODE_SEGMENT: usize = 0;
+/// address of the first word of the stack segment, initial value of sp
+pub const STACK_SEGMENT: usize = 4096;
+/// the stack cannot grow to this address or beyond
+pub const STACK_LIMIT: usize = 12 * 1024;
+/// highest address of the heap, which grows downwards towards STACK_LIMIT
+pub const HEAP_BASE: usize = MEMSIZE - 1;
+/// default number of instructions that [Vm::run] executes before giving up
+pub const DEFAULT_MAX_STEPS: usize = 1_000_000;
+
+/// Snapshot of all registers of the machine, including the internal
+/// pc (program counter) and ir (instruction register).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Registers {
+    pub ax: i16,
+    pub bx: i16,
+    pub cx: i16,
+    pub sp: i16,
+    pub bp: i16,
+    pub ma: i16,
+    pub pc: u16,
+    pub ir: u16,
+}
+
+impl Registers {
+    /// value of a register visible to software
+    pub fn get(&self, r: Reg) -> i16 {
+        match r {
+            Reg::Ax => self.ax,
+            Reg::Bx => self.bx,
+            Reg::Cx => self.cx,
+            Reg::Sp => self.sp,
+            Reg::Bp => self.bp,
+            Reg::Ma => self.ma,
+        }
+    }
+
+    /// mutable reference to a register visible to software
+    pub fn get_mut(&mut self, r: Reg) -> &mut i16 {
+        match r {
+            Reg::Ax => &mut self.ax,
+            Reg::Bx => &mut self.bx,
+            Reg::Cx => &mut self.cx,
+            Reg::Sp => &mut self.sp,
+            Reg::Bp => &mut self.bp,
+            Reg::Ma => &mut self.ma,
+        }
+    }
+} // impl Registers
+
+/// Runtime errors of the machine.  The first few correspond to the
+/// error messages of the C++ version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    StackOverflow,
+    StackUnderflow,
+    /// attempt to pop into the sp register
+    StackPointerCorruption,
+    /// the instruction word names one of the unused registers 6 or 7
+    InvalidOperand(u16),
+    /// memory access outside of the 32K words of RAM
+    SegmentationFault(u16),
+    DivideByZero,
+    /// the program executed more than the allowed number of instructions
+    StepLimit(usize),
+    /// the program does not fit into the code segment
+    ProgramTooLarge(usize),
+    /// an instruction of the program cannot be encoded
+    Encode(EncodeError),
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmError::StackOverflow => write!(f, "Stack Overflow"),
+            VmError::StackUnderflow => write!(f, "Stack Underflow"),
+            VmError::StackPointerCorruption => write!(f, "Stack Pointer Corruption"),
+            VmError::InvalidOperand(w) => write!(f, "Invalid Operand in instruction {:#06x}", w),
+            VmError::SegmentationFault(a) => write!(
+                f,
+                "Segmentation Fault at address {}. It's not my fault, it's your fault.",
+                a
+            ),
+            VmError::DivideByZero => write!(f, "Division by Zero"),
+            VmError::StepLimit(n) => write!(f, "Program did not halt within {} steps", n),
+            VmError::ProgramTooLarge(n) => {
+                write!(f, "Program of {} instructions does not fit in code segment", n)
+            }
+            VmError::Encode(e) => write!(f, "{}", e),
+        }
+    }
+} // impl Display for VmError
+
+impl std::error::Error for VmError {}
+
+impl From<EncodeError> for VmError {
+    fn from(e: EncodeError) -> Self {
+        VmError::Encode(e)
+    }
+}
+
+/// The virtual machine: registers plus 32K words of RAM.  A program is
+/// loaded into the code segment starting at address 1 and the machine
+/// halts when pc moves past the last loaded instruction, as in the C++
+/// version.
+pub struct Vm {
+    pub regs: Registers,
+    pub ram: Vec<i16>,
+    /// address following the last instruction loaded
+    pub end: u16,
+    /// maximum number of instructions executed by [Vm::run]
+    pub max_steps: usize,
+    steps: usize,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    /// creates a machine with zeroed memory and registers, sp pointing to
+    /// the start of the stack segment and pc at address 1.
+    pub fn new() -> Self {
+        Vm {
+            regs: Registers {
+                sp: STACK_SEGMENT as i16,
+                pc: 1,
+                ..Registers::default()
+            },
+            ram: vec![0; MEMSIZE],
+            end: 1,
+            max_steps: DEFAULT_MAX_STEPS,
+            steps: 0,
+        }
+    } //new
+
+    /// Encodes and loads a program at the start of the code segment.
+    pub fn load(&mut self, code: &[Instr]) -> Result<(), VmError> {
+        let words = code.iter().map(|i| i.encode()).collect::<Result<Vec<u16>, _>>()?;
+        self.load_words(&words)
+    } //load
+
+    /// Loads already-encoded instruction words at address 1 of the code
+    /// segment and resets pc to 1.
+    pub fn load_words(&mut self, words: &[u16]) -> Result<(), VmError> {
+        if words.len() + 1 > STACK_SEGMENT {
+            return Err(VmError::ProgramTooLarge(words.len()));
+        }
+        for (i, w) in words.iter().enumerate() {
+            self.ram[CODE_SEGMENT + 1 + i] = *w as i16;
+        }
+        self.end = (words.len() + 1) as u16;
+        self.regs.pc = 1;
+        self.steps = 0;
+        Ok(())
+    } //load_words
+
+    /// true when pc has moved past the end of the program
+    pub fn halted(&self) -> bool {
+        self.regs.pc >= self.end
+    }
+
+    /// number of instructions executed since the program was loaded
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// value on top of the stack, if the stack is not empty
+    pub fn tos(&self) -> Option<i16> {
+        let sp = self.regs.sp as usize;
+        if sp > STACK_SEGMENT && sp <= STACK_LIMIT {
+            Some(self.ram[sp - 1])
+        } else {
+            None
+        }
+    } //tos
+
+    /// contents of the stack segment, from the bottom of the stack to the top
+    pub fn stack(&self) -> &[i16] {
+        let sp = (self.regs.sp as usize).clamp(STACK_SEGMENT, STACK_LIMIT);
+        &self.ram[STACK_SEGMENT..sp]
+    }
+
+    fn addr(&self, a: u16) -> Result<usize, VmError> {
+        if (a as usize) < MEMSIZE {
+            Ok(a as usize)
+        } else {
+            Err(VmError::SegmentationFault(a))
+        }
+    }
+
+    fn value(&self, src: Operand) -> i16 {
+        match src {
+            Operand::Imm(n) => n as i16,
+            Operand::Reg(r) => self.regs.get(r),
+        }
+    }
+
+    fn push(&mut self, v: i16) -> Result<(), VmError> {
+        let tos = self.regs.sp as u16;
+        if tos as usize >= STACK_LIMIT {
+            return Err(VmError::StackOverflow);
+        }
+        let a = self.addr(tos)?;
+        self.ram[a] = v;
+        self.regs.sp = tos.wrapping_add(1) as i16;
+        Ok(())
+    } //push
+
+    fn pop(&mut self) -> Result<i16, VmError> {
+        let tos = self.regs.sp as u16;
+        if tos as usize <= STACK_SEGMENT {
+            return Err(VmError::StackUnderflow);
+        }
+        let a = self.addr(tos - 1)?;
+        self.regs.sp = (tos - 1) as i16;
+        Ok(self.ram[a])
+    } //pop
+
+    /// Fetches, decodes and executes the instruction at pc, then increments
+    /// pc.  Branches set pc to target-1 to offset the automatic increment.
+    pub fn step(&mut self) -> Result<(), VmError> {
+        let pc = self.addr(self.regs.pc)?;
+        self.regs.ir = self.ram[pc] as u16;
+        let ir = self.regs.ir;
+        let inst = Instr::decode(ir).ok_or(VmError::InvalidOperand(ir))?;
+        match inst {
+            Instr::Nop => {}
+            Instr::Add(s, d) => {
+                let v = self.value(s);
+                let r = self.regs.get_mut(d);
+                *r = r.wrapping_add(v);
+            }
+            Instr::Sub(s, d) => {
+                let v = self.value(s);
+                let r = self.regs.get_mut(d);
+                *r = r.wrapping_sub(v);
+            }
+            Instr::Mult(s, d) => {
+                let v = self.value(s);
+                let r = self.regs.get_mut(d);
+                *r = r.wrapping_mul(v);
+            }
+            Instr::Div(s, d) => {
+                let v = self.value(s);
+                if v == 0 {
+                    return Err(VmError::DivideByZero);
+                }
+                let tmp = self.regs.get(d);
+                *self.regs.get_mut(d) = tmp.wrapping_div(v);
+                self.regs.cx = tmp.wrapping_rem(v); // remainder always in cx
+            }
+            Instr::Push(s) => {
+                let v = self.value(s);
+                self.push(v)?;
+            }
+            Instr::Pop(d) => {
+                if d == Reg::Sp {
+                    return Err(VmError::StackPointerCorruption);
+                }
+                let v = self.pop()?;
+                *self.regs.get_mut(d) = v;
+            }
+            Instr::Mov(s, d) => {
+                let v = self.value(s);
+                *self.regs.get_mut(d) = v;
+            }
+            Instr::Load(d) => {
+                let a = self.addr(self.regs.ma as u16)?;
+                *self.regs.get_mut(d) = self.ram[a];
+            }
+            Instr::Store(s) => {
+                let v = self.value(s);
+                let a = self.addr(self.regs.ma as u16)?;
+                self.ram[a] = v;
+            }
+            Instr::Jmp(t) => self.regs.pc = t.wrapping_sub(1),
+            Instr::Jnz(t) if self.regs.cx != 0 => self.regs.pc = t.wrapping_sub(1),
+            Instr::Jz(t) if self.regs.cx == 0 => self.regs.pc = t.wrapping_sub(1),
+            Instr::Jn(t) if self.regs.cx < 0 => self.regs.pc = t.wrapping_sub(1),
+            Instr::Jnz(_) | Instr::Jz(_) | Instr::Jn(_) => {}
+            Instr::Call(t) => {
+                self.push(self.regs.pc as i16)?;
+                self.regs.pc = t.wrapping_sub(1);
+            }
+            Instr::Ret => {
+                self.regs.pc = self.pop()? as u16; // allow it to increment
+            }
+        } //match
+        self.regs.pc = self.regs.pc.wrapping_add(1);
+        self.steps += 1;
+        Ok(())
+    } //step
+
+    /// Runs the loaded program until it halts, returning the final state of
+    /// the registers.  Gives up with [VmError::StepLimit] after
+    /// `max_steps` instructions, since branches allow infinite loops.
+    pub fn run(&mut self) -> Result<Registers, VmError> {
+        while !self.halted() {
+            if self.steps >= self.max_steps {
+                return Err(VmError::StepLimit(self.max_steps));
+            }
+            self.step()?;
+        }
+        Ok(self.regs)
+    } //run
+} // impl Vm
+
+/// Loads a program into a fresh machine and runs it to completion,
+/// returning the machine so that its registers and stack can be examined.
+pub fn execute(code: &[Instr]) -> Result<Vm, VmError> {
+    let mut vm = Vm::new();
+    vm.load(code)?;
+    vm.run()?;
+    Ok(vm)
+} //execute
+
+impl Display for Registers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ax={}, bx={}, cx={}, sp={}, bp={}, ma={}, pc={}",
+            self.ax, self.bx, self.cx, self.sp as u16, self.bp as u16, self.ma as u16, self.pc
+        )
+    }
+} // impl Display for Registers
+
+/// Prints the status line in the format of the C++ trace, including the
+/// top of the stack if it's not empty.
+impl Display for Vm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.regs)?;
+        if let Some(t) = self.tos() {
+            write!(f, ", tos={}", t)?;
+        }
+        Ok(())
+    }
+} // impl Display for Vm
@@ -0,0 +1,73 @@
+//! Assembling `.am16` text into machine words and disassembling it back.
+
+use csc_7b_fc::am16::{EncodeError, Instr};
+use csc_7b_fc::assembler::*;
+use csc_7b_fc::vm16::Vm;
+
+// every opcode, with register and immediate operands
+const PROGRAM: &str = "# all opcodes
+nop
+push 2047
+push cx
+pop ax
+mov 255 bx
+mov sp bp
+add 1 ax
+sub bx ax
+mult 3 cx
+div bx ax
+mov 200 ma
+store 12
+store ax
+load cx
+jmp 17
+jnz 4095
+jz 1
+jn 2
+call 19
+ret
+.
+anything after the dot is ignored
+";
+
+#[test]
+fn assemble_and_disassemble_round_trip() {
+    let words = assemble(PROGRAM).unwrap();
+    assert_eq!(words.len(), 20);
+    let text = disassemble(&words).unwrap();
+    assert!(text.starts_with("nop\npush 2047\npush cx\n"), "{}", text);
+    assert_eq!(assemble(&text).unwrap(), words);
+    assert_eq!(parse_program(&text).unwrap(), parse_program(PROGRAM).unwrap());
+    // the sample program of vm16 runs from its words
+    let sample = assemble(include_str!("../vm16/sample.am16")).unwrap();
+    assert_eq!(assemble(&disassemble(&sample).unwrap()).unwrap(), sample);
+    let mut vm = Vm::new();
+    vm.load_words(&sample).unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.tos(), Some(12));
+} //assemble_and_disassemble_round_trip
+
+#[test]
+fn out_of_range_immediates_and_bad_destinations() {
+    let kind = |line: &str| parse_line(line).unwrap_err();
+    let max = |line: &str| match kind(line) {
+        AsmErrorKind::Encode(EncodeError::ImmediateOutOfRange { max, .. }) => max,
+        other => panic!("{} gave {:?}", line, other),
+    };
+    assert_eq!(max("add 256 ax"), 255);
+    assert_eq!(max("mov 256 bx"), 255);
+    assert_eq!(max("sub -1 cx"), 255);
+    assert_eq!(max("push 2048"), 2047);
+    assert_eq!(max("store 2048"), 2047);
+    assert!(parse_line("add 255 ax").is_ok() && parse_line("push 2047").is_ok());
+    assert_eq!(kind("add 1 5"), AsmErrorKind::InvalidDestination("5".into()));
+    assert_eq!(kind("mov ax pc"), AsmErrorKind::InvalidDestination("pc".into()));
+    assert_eq!(kind("pop sp"), AsmErrorKind::InvalidDestination("sp".into()));
+    assert_eq!(kind("load 3"), AsmErrorKind::InvalidDestination("3".into()));
+    let far = EncodeError::TargetOutOfRange(Instr::Jmp(4096));
+    assert_eq!(kind("jmp 4096"), AsmErrorKind::Encode(far));
+    // errors carry the line number
+    let e = assemble("push 1\n\n# comment\nadd 300 ax\n").unwrap_err();
+    assert_eq!(e.line, 4);
+    assert_eq!(e.to_string(), "line 4: immediate operand of `add 300 ax` must be in range 0-255");
+} //out_of_range_immediates_and_bad_destinations
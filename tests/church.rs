@@ -0,0 +1,66 @@
+//! Arithmetic expressions computed as lambda terms: the normal form of the
+//! Church encoding of an expression is the numeral of its value.
+
+use csc_7b_fc::lambda::*;
+use csc_7b_fc::{eval, parse_str};
+
+// the value of the encoding of s, found in normal order
+fn church_value(s: &str) -> Option<usize> {
+    let t = encode(&parse_str(s).unwrap()).unwrap();
+    decode(&normalize(&t, Strategy::NormalOrder, DEFAULT_MAX_STEPS).unwrap())
+}
+
+#[test]
+fn numerals_decode() {
+    for n in 0..5 {
+        assert_eq!(decode(&church(n)), Some(n));
+    }
+    assert_eq!(decode(&parse_term("\\g y.g (g y)").unwrap()), Some(2));
+    for s in ["\\f.f", "\\x x.x", "\\f x.f x x", "\\f x.g x", "\\f x.f (\\y.x)"] {
+        assert_eq!(decode(&parse_term(s).unwrap()), None, "{}", s);
+    }
+} //numerals_decode
+
+#[test]
+fn encoded_expressions_compute_their_values() {
+    let exprs = [
+        "0", "7", "2+3", "2*3", "3*0", "5-2", "4-4", "(1+2)*(3+1)",
+        "2*3+4*1", "(6-2)*2+1", "3*(4-1)-2*2", "((1+1)*(1+1))*(2+1)",
+    ];
+    for s in exprs {
+        let value = eval(&parse_str(s).unwrap()).unwrap();
+        assert_eq!(church_value(s), Some(value as usize), "{}", s);
+    }
+    // the encoding is closed and uses the prelude combinators
+    let t = encode(&parse_str("1+2").unwrap()).unwrap();
+    assert!(t.free_vars().is_empty());
+    let prelude = Prelude::standard();
+    assert!(alpha_eq(&t, &prelude.parse("ADD ONE church2").unwrap()));
+} //encoded_expressions_compute_their_values
+
+#[test]
+fn subtraction_is_truncated_and_others_fail() {
+    assert_eq!(church_value("2-3"), Some(0));
+    assert_eq!(church_value("1-5+2"), Some(2));
+    let error = |s: &str| encode(&parse_str(s).unwrap()).unwrap_err().to_string();
+    assert_eq!(error("6/2"), "cannot encode 6/2 with Church numerals");
+    assert!(encode(&parse_str("let x = 1 in x").unwrap()).is_err());
+    assert!(encode(&csc_7b_fc::Expr::Val(-1)).is_err());
+} //subtraction_is_truncated_and_others_fail
+
+#[test]
+fn numerals_are_bounded() {
+    // the largest numerals still reduce within the stack of a test thread
+    let max = MAX_NUMERAL.to_string();
+    assert_eq!(church_value(&max), Some(MAX_NUMERAL));
+    assert_eq!(church_value("10*100"), Some(1000));
+    assert_eq!(church_value("999+1"), Some(1000));
+    let fails = |s: &str| {
+        let e = parse_str(s).unwrap();
+        assert!(matches!(encode(&e), Err(LambdaError::NotEncodable(_))), "{}", s);
+    };
+    // a literal, a result, or a value on the way to a small result
+    for s in ["1001", "50000", "300*300", "1000+1", "(600+600)-500", "0*(2000-1)"] {
+        fails(s);
+    }
+} //numerals_are_bounded
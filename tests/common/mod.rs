@@ -0,0 +1,83 @@
+//! Random expression generator and other helpers shared by the
+//! integration tests.
+
+// each test file uses only some of the helpers
+#![allow(dead_code)]
+
+use std::ops::RangeInclusive;
+
+use csc_7b_fc::ArithMode::{self, *};
+use csc_7b_fc::Expr::{self, *};
+
+/// All the arithmetic modes
+pub const MODES: [ArithMode; 4] = [Checked, Wrapping, Saturating, Am16];
+
+/// xorshift pseudo-random generator, so that failures can be reproduced
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
+
+/// Random constant from one of the ranges, each range chosen equally often
+pub fn constant(rng: &mut Rng, ranges: &[RangeInclusive<i32>]) -> i32 {
+    let r = &ranges[rng.below(ranges.len() as u64) as usize];
+    let (lo, hi) = (*r.start() as i64, *r.end() as i64);
+    (lo + rng.below((hi - lo + 1) as u64) as i64) as i32
+} //constant
+
+pub fn b(e: Expr) -> Box<Expr> {
+    Box::new(e)
+}
+
+/// Random expression of the given depth using only the variables in
+/// scope, with leaves drawn from the constant generator.
+pub fn random_expr(
+    rng: &mut Rng,
+    depth: u32,
+    constant: fn(&mut Rng) -> i32,
+    scope: &mut Vec<String>,
+) -> Expr {
+    if depth == 0 || rng.below(5) == 0 {
+        return match scope.len() {
+            n if n > 0 && rng.below(3) == 0 => Var(scope[rng.below(n as u64) as usize].clone()),
+            _ => Val(constant(rng)),
+        };
+    }
+    let mut sub = |rng: &mut Rng| b(random_expr(rng, depth - 1, constant, scope));
+    match rng.below(18) {
+        0 | 1 => Plus(sub(rng), sub(rng)),
+        2 => Minus(sub(rng), sub(rng)),
+        3 | 4 => Times(sub(rng), sub(rng)),
+        5 => Divide(sub(rng), sub(rng)),
+        6 => Mod(sub(rng), sub(rng)),
+        7 => Neg(sub(rng)),
+        8 => Less(sub(rng), sub(rng)),
+        9 => LessEq(sub(rng), sub(rng)),
+        10 => Equal(sub(rng), sub(rng)),
+        11 => NotEqual(sub(rng), sub(rng)),
+        12 => And(sub(rng), sub(rng)),
+        13 => Or(sub(rng), sub(rng)),
+        14 => If(sub(rng), sub(rng), sub(rng)),
+        15 => Not(sub(rng)),
+        // small exponents keep the loop of the compiled code short
+        16 => {
+            let base = sub(rng);
+            let k = rng.below(7) as i32 - 2;
+            Pow(base, b(if k < 0 { Neg(b(Val(-k))) } else { Val(k) }))
+        }
+        _ => {
+            let x = format!("x{}", scope.len());
+            let e1 = random_expr(rng, depth - 1, constant, scope);
+            scope.push(x.clone());
+            let e2 = random_expr(rng, depth - 1, constant, scope);
+            scope.pop();
+            Let(x, b(e1), b(e2))
+        }
+    }
+} //random_expr
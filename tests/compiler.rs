@@ -0,0 +1,161 @@
+//! The code generated for expressions, run on the AM16 machine.
+
+use csc_7b_fc::am16::{listing, Instr};
+use csc_7b_fc::vm16::{execute, VmError};
+use csc_7b_fc::Expr::*;
+use csc_7b_fc::compiler::{instruction_counts, CompileError};
+use csc_7b_fc::{compile, compile_with, eval_mode, Backend, parse_str, ArithMode, Expr};
+
+fn b(e: Expr) -> Box<Expr> {
+    Box::new(e)
+}
+
+// value left by the compiled code, which must be alone on the stack
+fn run(code: &[Instr]) -> Result<i16, VmError> {
+    let vm = execute(code)?;
+    assert_eq!(vm.stack().len(), 1, "{:?}", code);
+    Ok(vm.tos().unwrap())
+}
+
+fn value(s: &str) -> Result<i16, VmError> {
+    run(&compile(&parse_str(s).unwrap()).unwrap())
+}
+
+#[test]
+fn compiled_arithmetic_computes_the_expression() {
+    // the left operand is the first one pushed, but sub and div take the
+    // right one as their source
+    assert_eq!(run(&compile(&Minus(b(Val(1)), b(Val(5)))).unwrap()), Ok(-4));
+    assert_eq!(run(&compile(&Divide(b(Val(20)), b(Val(6)))).unwrap()), Ok(3));
+    assert_eq!(value("6/20"), Ok(0));
+    // div leaves the remainder in cx, which is what % pushes
+    assert_eq!(value("20%6"), Ok(2));
+    assert_eq!(value("-7%3"), Ok(-1));
+    assert_eq!(value("3*20-9%2"), Ok(59));
+    assert_eq!(value("10-2-3"), Ok(5));
+    assert_eq!(value("100/10/5"), Ok(2));
+    assert_eq!(value("-(4-9)*2"), Ok(10));
+    assert_eq!(value("7/(3-3)"), Err(VmError::DivideByZero));
+    assert_eq!(value("7%0"), Err(VmError::DivideByZero));
+} //compiled_arithmetic_computes_the_expression
+
+#[test]
+fn variables_live_in_the_heap() {
+    assert_eq!(value("let x = 1 in let x = x + 1 in x * 10"), Ok(20));
+    assert_eq!(value("let x = 2 in (let x = 5 in x) + x"), Ok(7));
+    assert_eq!(value("let a = 3 in let b = a * a in let c = b - a in c * a"), Ok(18));
+    let error = |s: &str| compile(&parse_str(s).unwrap()).unwrap_err();
+    assert_eq!(error("y + 1"), CompileError::Unbound("y".into()));
+    assert_eq!(error("(let y = 1 in y) + y"), CompileError::Unbound("y".into()));
+    assert_eq!(error("let x = x in x"), CompileError::Unbound("x".into()));
+} //variables_live_in_the_heap
+
+#[test]
+fn too_many_nested_lets_exhaust_the_heap() {
+    // one slot for each of the 20480 words between the stack and the top
+    // of memory; the compiler recurses once for each let, so it needs a
+    // larger stack than the test thread has
+    let nested = |n: usize| {
+        (0..n).fold(Val(0), |body, i| Let(format!("x{}", i), b(Val(i as i32)), b(body)))
+    };
+    let compiled = std::thread::Builder::new()
+        .stack_size(256 << 20)
+        .spawn(move || (compile(&nested(20480)).err(), compile(&nested(20481)).err()))
+        .unwrap()
+        .join()
+        .unwrap();
+    // the last slot is still available, but the code is too long
+    assert!(matches!(compiled.0, Some(CompileError::ProgramTooLarge(_))));
+    assert_eq!(compiled.1, Some(CompileError::HeapExhausted));
+} //too_many_nested_lets_exhaust_the_heap
+
+#[test]
+fn logical_operators_short_circuit() {
+    // the division is skipped by a branch, so it cannot trap
+    assert_eq!(value("0 && 1/0"), Ok(0));
+    assert_eq!(value("1 || 1/0"), Ok(1));
+    assert_eq!(value("!(0 && 1/0) + (2 < 3 || 4 % 0)"), Ok(2));
+    assert_eq!(value("if 0 then 1/0 else 5"), Ok(5));
+    assert_eq!(value("if 3 - 3 == 0 then 6 else 1/0"), Ok(6));
+    // without the short circuit the division runs
+    assert_eq!(value("1 && 1/0"), Err(VmError::DivideByZero));
+    assert_eq!(value("0 || 1/0"), Err(VmError::DivideByZero));
+    // booleans are 0 or 1 whatever the values of the operands
+    assert_eq!(value("7 && 2"), Ok(1));
+    assert_eq!(value("0 || -3"), Ok(1));
+    assert_eq!(value("!5"), Ok(0));
+} //logical_operators_short_circuit
+
+#[test]
+fn comparisons_wrap_around_in_am16_mode() {
+    // a comparison branches on the sign of the 16-bit difference, which
+    // overflows for operands far apart, and the Am16 mode of eval agrees
+    for (s, wrapped) in [
+        ("0-30000 < 30000", 0),
+        ("30000 < 0-30000", 1),
+        ("30000 <= 0-30000", 1),
+        ("32767+1 < 0", 1),
+        ("0-20000 < 20000", 0),
+        ("100 < 200", 1),
+        ("200 <= 200", 1),
+        ("0-5 == 0-2-3", 1),
+    ] {
+        let e = parse_str(s).unwrap();
+        assert_eq!(eval_mode(&e, ArithMode::Am16), Ok(wrapped), "{}", s);
+        assert_eq!(run(&compile(&e).unwrap()), Ok(wrapped as i16), "{}", s);
+    }
+    let e = parse_str("0-30000 < 30000").unwrap();
+    assert_eq!(eval_mode(&e, ArithMode::Checked), Ok(1));
+} //comparisons_wrap_around_in_am16_mode
+
+#[test]
+fn division_in_the_register_backend_keeps_cx() {
+    // b*c is kept in cx while d/3 is computed in bx, and div overwrites cx
+    // with the remainder, so cx must be saved around it
+    let e = parse_str("let a = 7 in let b = 30 in let c = 10 in let d = 95 in a*b + (d/3 - b*c)")
+        .unwrap();
+    let code = compile_with(&e, Backend::Registers).unwrap();
+    assert!(listing(&code).contains("push cx\ndiv 3 bx\npop cx\n"), "{}", listing(&code));
+    assert_eq!(eval_mode(&e, ArithMode::Am16), Ok(-59));
+    assert_eq!(run(&code), Ok(-59));
+} //division_in_the_register_backend_keeps_cx
+
+#[test]
+fn registers_shorten_the_code_of_deep_trees() {
+    // a balanced tree of depth 6 over the variables of a few lets
+    fn tree(depth: usize, i: usize) -> String {
+        match depth {
+            0 => ["a", "b", "c", "d"][i % 4].to_string(),
+            _ => {
+                let op = ["+", "*", "-", "/"][depth % 4];
+                format!("({} {} {})", tree(depth - 1, 2 * i), op, tree(depth - 1, 2 * i + 1))
+            }
+        }
+    }
+    let s = format!("let a = 7 in let b = 3 in let c = 11 in let d = 2 in {}", tree(6, 0));
+    let e = parse_str(&s).unwrap();
+    let (stack, registers) = instruction_counts(&e).unwrap();
+    assert!(registers < stack, "{} registers, {} stack", registers, stack);
+    let expected = eval_mode(&e, ArithMode::Am16).ok().map(|v| v as i16);
+    assert!(expected.is_some(), "{}", s);
+    assert_eq!(run(&compile_with(&e, Backend::Registers).unwrap()).ok(), expected);
+    assert_eq!(run(&compile_with(&e, Backend::Stack).unwrap()).ok(), expected);
+} //registers_shorten_the_code_of_deep_trees
+
+#[test]
+fn constants_cover_the_16_bit_range() {
+    // 2048 and the negative ones are too big for a push immediate, and
+    // are built in a register from bytes
+    for n in [-32768, -32767, -129, -1, 0, 255, 256, 2047, 2048, 32639, 32767] {
+        for backend in [Backend::Stack, Backend::Registers] {
+            let code = compile_with(&Val(n), backend).unwrap();
+            assert_eq!(run(&code), Ok(n as i16), "{} {:?}", n, backend);
+        }
+    }
+    for n in [32768, -32769, 40000] {
+        for backend in [Backend::Stack, Backend::Registers] {
+            let e = Plus(b(Val(1)), b(Val(n)));
+            assert_eq!(compile_with(&e, backend), Err(CompileError::ConstantOutOfRange(n)));
+        }
+    }
+} //constants_cover_the_16_bit_range
@@ -0,0 +1,56 @@
+//! De Bruijn indices, alpha-equivalence and the comparison of lambda
+//! terms by their normal forms, such as `S K K` and `I`.
+
+use csc_7b_fc::lambda::*;
+
+fn term(s: &str) -> Term {
+    parse_term(s).unwrap()
+}
+
+#[test]
+fn indices_count_the_lambdas_in_between() {
+    assert_eq!(term("\\x y.x").to_de_bruijn().to_string(), "\\.\\.1");
+    assert_eq!(term("\\x.x (\\y.x y) z").to_de_bruijn().to_string(), "\\.0 (\\.1 0) z");
+    let t = term("\\f x.f (\\f.f x)");
+    assert_eq!(t.to_de_bruijn().to_string(), "\\.\\.1 (\\.0 1)");
+    // back to names, which only differ from the original ones
+    let back = t.to_de_bruijn().to_term();
+    assert_eq!(back, term("\\x0 x1.x0 (\\x2.x2 x1)"));
+    assert!(alpha_eq(&back, &t));
+    // free variables named like the bound ones are not captured
+    let t = term("\\y.y x0 x1");
+    assert_eq!(t.to_de_bruijn().to_term(), term("\\x_0.x_0 x0 x1"));
+    let dangling = DeBruijn::Lam(Box::new(DeBruijn::Bound(2)));
+    assert_eq!(dangling.to_term(), term("\\x0._1"));
+} //indices_count_the_lambdas_in_between
+
+#[test]
+fn alpha_equivalence_ignores_bound_names() {
+    assert!(alpha_eq(&term("\\x.x"), &term("\\y.y")));
+    assert!(alpha_eq(&term("\\x y.y x"), &term("\\a b.b a")));
+    assert!(!alpha_eq(&term("\\x y.x"), &term("\\x y.y")));
+    // free variables must have the same names
+    assert!(!alpha_eq(&term("\\x.z"), &term("\\x.w")));
+    assert!(!alpha_eq(&term("\\x.z"), &term("\\z.z")));
+} //alpha_equivalence_ignores_bound_names
+
+#[test]
+fn normal_forms_of_the_prelude() {
+    let prelude = Prelude::standard();
+    let same = |a: &str, b: &str| {
+        beta_eq(&prelude.parse(a).unwrap(), &prelude.parse(b).unwrap(), DEFAULT_MAX_STEPS)
+            .unwrap()
+    };
+    assert!(same("S K K", "I"));
+    assert!(same("ZERO", "FALSE"));
+    assert!(same("ADD church2 church3", "SUCC (SUCC church3)"));
+    assert!(same("TIMES church2 church3", "ADD church3 church3"));
+    assert!(same("PRED ONE", "ZERO"));
+    assert!(same("NOT (NOT TRUE)", "TRUE"));
+    assert!(!same("TRUE", "FALSE"));
+    let five = normalize(&prelude.parse("ADD church2 church3").unwrap(), Strategy::NormalOrder, 100);
+    assert!(alpha_eq(&five.unwrap(), &church(5)));
+    // both terms need a normal form
+    let omega = term("(\\x.x x) (\\x.x x)");
+    assert_eq!(beta_eq(&omega, &omega, 50), Err(LambdaError::StepLimit(50)));
+} //normal_forms_of_the_prelude
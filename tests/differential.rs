@@ -0,0 +1,122 @@
+//! Differential testing of the interpreter against the compiler: random
+//! expressions are evaluated with 16-bit arithmetic ([ArithMode::Am16])
+//! and also compiled and run on the AM16 machine, with both backends.
+//! The two must agree on the value, or both fail with a division by zero.
+//! A disagreement is shrunk to a small counterexample, printed with the
+//! `Display` impl of `Expr`.
+
+mod common;
+
+use std::ops::RangeInclusive;
+
+use common::{b, constant, random_expr, Rng};
+use csc_7b_fc::vm16::{execute, VmError};
+use csc_7b_fc::Expr::{self, *};
+use csc_7b_fc::{compile_with, eval_mode, ArithMode, Backend, EvalError};
+
+// small numbers, ALU and push immediates, and 16-bit constants
+const CONSTANTS: [RangeInclusive<i32>; 4] = [0..=3, 0..=255, 0..=2047, -32768..=32767];
+
+// e with its children replaced by kids, in order
+fn rebuild(e: &Expr, kids: Vec<Expr>) -> Expr {
+    let mut k = kids.into_iter().map(b);
+    let mut next = || k.next().unwrap();
+    match e {
+        Val(n) => Val(*n),
+        Var(x) => Var(x.clone()),
+        Neg(_) => Neg(next()),
+        Not(_) => Not(next()),
+        Plus(..) => Plus(next(), next()),
+        Minus(..) => Minus(next(), next()),
+        Times(..) => Times(next(), next()),
+        Divide(..) => Divide(next(), next()),
+        Mod(..) => Mod(next(), next()),
+        Pow(..) => Pow(next(), next()),
+        Less(..) => Less(next(), next()),
+        LessEq(..) => LessEq(next(), next()),
+        Equal(..) => Equal(next(), next()),
+        NotEqual(..) => NotEqual(next(), next()),
+        And(..) => And(next(), next()),
+        Or(..) => Or(next(), next()),
+        Let(x, ..) => Let(x.clone(), next(), next()),
+        If(..) => If(next(), next(), next()),
+        Call(f, args) => Call(f.clone(), args.iter().map(|_| *next()).collect()),
+    }
+} //rebuild
+
+// smaller variants of e: its children, simpler constants, and e with one
+// child replaced by one of its own smaller variants
+fn shrinks(e: &Expr) -> Vec<Expr> {
+    let kids = e.children();
+    let mut out: Vec<Expr> = kids.iter().map(|&k| k.clone()).collect();
+    if let Val(n) = e {
+        let closer = |m: &i32| m.unsigned_abs() < n.unsigned_abs();
+        out.extend([0, 1, n / 2].into_iter().filter(closer).map(Val));
+    }
+    for (i, k) in kids.iter().enumerate() {
+        for smaller in shrinks(k) {
+            let mut replaced: Vec<Expr> = kids.iter().map(|&k| k.clone()).collect();
+            replaced[i] = smaller;
+            out.push(rebuild(e, replaced));
+        }
+    }
+    out
+} //shrinks
+
+// Some(description) when the interpreter and the compiled program disagree
+fn disagreement(e: &Expr, backend: Backend) -> Option<String> {
+    let expected = eval_mode(e, ArithMode::Am16);
+    // shrinking may unbind variables, which the compiler rejects too; any
+    // other compile error on a closed expression is a disagreement
+    if let Err(EvalError::Unbound(_)) = expected {
+        return None;
+    }
+    let code = match compile_with(e, backend) {
+        Ok(code) => code,
+        Err(err) => return Some(format!("eval: {:?}, {:?} backend: {}", expected, backend, err)),
+    };
+    let actual = execute(&code).map(|vm| (vm.tos(), vm.stack().len()));
+    let agree = match (&expected, &actual) {
+        (Ok(v), Ok((tos, depth))) => *tos == Some(*v as i16) && *depth == 1,
+        (Err(EvalError::DivideByZero), Err(VmError::DivideByZero)) => true,
+        _ => false,
+    };
+    if agree {
+        None
+    } else {
+        Some(format!(
+            "eval: {:?}, {:?} backend: {:?}",
+            expected, backend, actual
+        ))
+    }
+} //disagreement
+
+// greedily replaces e by smaller variants that still fail
+fn shrink(mut e: Expr, backend: Backend) -> Expr {
+    while let Some(smaller) = shrinks(&e)
+        .into_iter()
+        .find(|s| disagreement(s, backend).is_some())
+    {
+        e = smaller;
+    }
+    e
+} //shrink
+
+#[test]
+fn eval_agrees_with_compiled_code() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..3000 {
+        let depth = 1 + rng.below(6) as u32;
+        let e = random_expr(&mut rng, depth, |r| constant(r, &CONSTANTS), &mut Vec::new());
+        for backend in [Backend::Stack, Backend::Registers] {
+            if disagreement(&e, backend).is_some() {
+                let minimal = shrink(e, backend);
+                panic!(
+                    "counterexample: {}\n{}",
+                    minimal,
+                    disagreement(&minimal, backend).unwrap_or_default()
+                );
+            }
+        }
+    }
+} //eval_agrees_with_compiled_code
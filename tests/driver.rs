@@ -0,0 +1,127 @@
+//! The command-line compiler: output modes, diagnostics and exit codes.
+
+use csc_7b_fc::assembler::disassemble;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+// runs the compiler with the given arguments and standard input
+fn driver(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_csc_7b_fc"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+} //driver
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[test]
+fn each_line_of_input_is_an_expression() {
+    let out = driver(&["--emit", "run"], "1+2\n\n# comment\n2^10\n");
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(text(&out.stdout), "3\n1024\n");
+    let out = driver(&["--emit", "ast", "-e", "-4"], "");
+    assert_eq!(text(&out.stdout), "Neg(Val(4))\n");
+} //each_line_of_input_is_an_expression
+
+#[test]
+fn a_line_can_define_functions() {
+    let fact = "fact(n) = if n == 0 then 1 else n * fact(n-1); fact(6)";
+    for backend in ["--stack", "--registers"] {
+        let out = driver(&["--emit", "run", backend, "-e", fact], "");
+        assert_eq!(text(&out.stdout), "720\n");
+    }
+    let asm = driver(&["-e", fact], "");
+    assert!(text(&asm.stdout).contains("call"));
+    let out = driver(&["-e", "g(1)"], "");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(text(&out.stderr).contains("unknown function g"));
+} //a_line_can_define_functions
+
+#[test]
+fn binary_output_disassembles_to_the_listing() {
+    let asm = driver(&["-e", "let x = 5 in x*x - 1"], "");
+    let bin = driver(&["--emit", "bin", "-e", "let x = 5 in x*x - 1"], "");
+    let words: Vec<u16> =
+        bin.stdout.chunks(2).map(|w| u16::from_le_bytes([w[0], w[1]])).collect();
+    assert_eq!(disassemble(&words).unwrap(), text(&asm.stdout));
+} //binary_output_disassembles_to_the_listing
+
+#[test]
+fn several_expressions_are_separate_programs() {
+    // each expression is a program at the same origin, so the listings are
+    // marked with their line, and -o writes a file for each expression
+    let two = "if 1 then 2 else 3\n# comment\nif 0 then 4 else 5\n";
+    let out = driver(&[], two);
+    assert_eq!(out.status.code(), Some(0));
+    let listing = text(&out.stdout);
+    let first = text(&driver(&[], "if 1 then 2 else 3\n").stdout);
+    let second = text(&driver(&[], "if 0 then 4 else 5\n").stdout);
+    assert_eq!(listing, format!("# <stdin>:1\n{}# <stdin>:3\n{}", first, second));
+    let path = std::env::temp_dir().join(format!("csc_7b_fc_{}.bin", std::process::id()));
+    let file = path.to_str().unwrap();
+    let out = driver(&["--emit", "bin", "-o", file], "7\n1+\n8\n");
+    assert_eq!(out.status.code(), Some(1));
+    for (k, e) in [(1, "7"), (3, "8")] {
+        let kth = format!("{}.{}", file, k);
+        let single = driver(&["--emit", "bin", "-e", e], "");
+        assert_eq!(std::fs::read(&kth).unwrap(), single.stdout);
+        std::fs::remove_file(&kth).unwrap();
+    }
+    assert!(!std::path::Path::new(&format!("{}.2", file)).exists());
+    // a single binary on stdout: the first one is still written
+    let out = driver(&["--emit", "bin", "-e", "7", "-e", "8"], "");
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(out.stdout.len(), 2);
+    assert!(text(&out.stderr).contains("-e:1: error: only one binary"), "{}", text(&out.stderr));
+    let out = driver(&["--emit", "run"], two);
+    assert_eq!(text(&out.stdout), "2\n5\n");
+} //several_expressions_are_separate_programs
+
+#[test]
+fn errors_are_located_and_set_the_exit_code() {
+    let out = driver(&["--emit", "run"], "1+\n2\n4/0\n");
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(text(&out.stdout), "2\n");
+    let errors = text(&out.stderr);
+    assert!(errors.contains("<stdin>:1:3: error: parse error"), "{}", errors);
+    assert!(errors.contains("<stdin>:3: error: runtime error"), "{}", errors);
+    assert_eq!(driver(&["--emit", "wasm"], "").status.code(), Some(2));
+    assert_eq!(driver(&["no/such/file"], "").status.code(), Some(2));
+} //errors_are_located_and_set_the_exit_code
+
+#[test]
+fn output_goes_to_a_file() {
+    let path = std::env::temp_dir().join(format!("csc_7b_fc_{}.am16", std::process::id()));
+    let file = path.to_str().unwrap();
+    let out = driver(&["-o", file, "-e", "7"], "");
+    assert_eq!(out.status.code(), Some(0));
+    assert!(out.stdout.is_empty());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "push 7\n");
+    std::fs::remove_file(&path).unwrap();
+} //output_goes_to_a_file
+
+#[test]
+fn optimization_simplifies_the_tree() {
+    let out = driver(&["-O", "--emit", "ast", "-e", "1+2*3"], "");
+    assert_eq!(text(&out.stdout), "Val(7)\n");
+    let out = driver(&["-O", "--counts", "--emit", "run", "-e", "let x = 4 in x*1 + 0*x"], "");
+    assert_eq!(text(&out.stdout), "4\n");
+    assert!(text(&out.stderr).contains("# -O removed 6 nodes"), "{}", text(&out.stderr));
+    let out = driver(&["-O", "--counts", "-e", "2*3 + 1/0"], "");
+    // push 1; pop ax is merged by the peephole optimizer
+    assert!(text(&out.stdout).starts_with("push 0\nmov 1 ax\npop bx\n"), "{}", text(&out.stdout));
+    assert!(text(&out.stderr).contains("# -O removed 2 instructions"), "{}", text(&out.stderr));
+    let f = "f(x) = x + (2 - 2); f(5)";
+    assert_eq!(text(&driver(&["-O", "--emit", "run", "-e", f], "").stdout), "5\n");
+    // runtime errors and constants out of range are kept
+    assert_eq!(driver(&["-O", "--emit", "run", "-e", "1/0"], "").status.code(), Some(1));
+    let out = driver(&["-O", "-e", "1 || 70000"], "");
+    assert!(text(&out.stderr).contains("constant 70000 does not fit"), "{}", text(&out.stderr));
+} //optimization_simplifies_the_tree
@@ -0,0 +1,63 @@
+//! The interpreter: its arithmetic modes, and variables bound by `let` or
+//! an environment.
+
+mod common;
+
+use common::MODES;
+use csc_7b_fc::ArithMode::*;
+use csc_7b_fc::Expr::{self, *};
+use csc_7b_fc::{eval_mode, eval_with, parse_str, Env, EvalError};
+
+// the results of the four modes, in the order of MODES
+fn results(e: &Expr) -> Vec<Result<i32, EvalError>> {
+    MODES.iter().map(|m| eval_mode(e, *m)).collect()
+}
+
+#[test]
+fn arithmetic_modes_handle_overflow() {
+    let e = parse_str("2147483647+1").unwrap();
+    let expected = [Err(EvalError::Overflow), Ok(i32::MIN), Ok(i32::MAX), Ok(0)];
+    assert_eq!(results(&e), expected);
+    let e = Divide(Box::new(Val(i32::MIN)), Box::new(Val(-1)));
+    // i32::MIN is 0 in 16 bits
+    let expected = [Err(EvalError::Overflow), Ok(i32::MIN), Ok(i32::MAX), Ok(0)];
+    assert_eq!(results(&e), expected);
+    // the 16-bit overflows of AM16
+    let e = Divide(Box::new(Val(-32768)), Box::new(Val(-1)));
+    assert_eq!(results(&e), [Ok(32768), Ok(32768), Ok(32768), Ok(-32768)]);
+    assert_eq!(eval_mode(&parse_str("32767+1").unwrap(), Am16), Ok(-32768));
+    assert_eq!(eval_mode(&parse_str("65536*2+3").unwrap(), Am16), Ok(3));
+    let e = parse_str("0-2147483647-2").unwrap();
+    let expected = [Err(EvalError::Overflow), Ok(i32::MAX), Ok(i32::MIN), Ok(-1)];
+    assert_eq!(results(&e), expected);
+    // division by zero is an error in every mode
+    for s in ["7%0", "7/0", "1+7%(3-3)"] {
+        let e = parse_str(s).unwrap();
+        assert_eq!(results(&e), vec![Err(EvalError::DivideByZero); 4], "{}", s);
+    }
+} //arithmetic_modes_handle_overflow
+
+fn value(s: &str, env: &Env) -> Result<i32, EvalError> {
+    eval_with(&parse_str(s).unwrap(), env)
+}
+
+#[test]
+fn let_bindings_shadow_and_go_out_of_scope() {
+    let none = Env::new();
+    assert_eq!(value("let x = 1 in let x = x + 1 in x * 10", &none), Ok(20));
+    assert_eq!(value("let x = 2 in (let x = 5 in x) + x", &none), Ok(7));
+    assert_eq!(value("let x = 3 in let y = x * x in y - x", &none), Ok(6));
+    let unbound = |x: &str| Err(EvalError::Unbound(x.into()));
+    assert_eq!(value("y + 1", &none), unbound("y"));
+    assert_eq!(value("(let y = 1 in y) + y", &none), unbound("y"));
+    assert_eq!(value("let x = x in x", &none), unbound("x"));
+    // the environment binds free variables, and let shadows them
+    let mut env = Env::new();
+    env.bind("x", 4);
+    env.bind("y", 5);
+    env.bind("x", 6);
+    assert_eq!(value("x * y", &env), Ok(30));
+    assert_eq!(value("let x = 1 in x + y", &env), Ok(6));
+    assert_eq!(value("(let y = 1 in y) + y", &env), Ok(6));
+    assert_eq!(env.lookup("x"), Some(6));
+} //let_bindings_shadow_and_go_out_of_scope
@@ -0,0 +1,135 @@
+//! Programs with function definitions: parsing, evaluation, and the AM16
+//! code with `call`, `ret` and bp-based frames, which must compute the
+//! same values as the interpreter with both backends.
+
+use csc_7b_fc::compiler::{compile_program, CompileError};
+use csc_7b_fc::number::{evaluate_program, Rational};
+use csc_7b_fc::program::{eval_program, parse_number_program, parse_program, Program};
+use csc_7b_fc::program::MAX_CALL_DEPTH;
+use csc_7b_fc::vm16::{execute, VmError};
+use csc_7b_fc::{parse_str, ArithMode, Backend, EvalError, Expected, SyntaxError};
+
+const FACT: &str = "fact(n) = if n == 0 then 1 else n * fact(n-1);";
+const FIB: &str = "fib(n) = if n < 2 then n else fib(n-1) + fib(n-2);";
+// the let in sum must keep one x for each call
+const SUM: &str = "sum(n) = let x = n in if x == 0 then 0 else sum(x-1) + x;";
+const EVEN: &str = "even(n) = if n == 0 then 1 else odd(n-1); odd(n) = if n == 0 then 0 else even(n-1);";
+const GCD: &str = "gcd(a, b) = if b == 0 then a else gcd(b, a % b);";
+
+fn program(s: &str) -> Program {
+    parse_program(s).unwrap()
+}
+
+// value computed by the VM with a backend, checking that the stack only
+// holds the result
+fn run(p: &Program, backend: Backend) -> Result<i16, VmError> {
+    let code = compile_program(p, backend).unwrap();
+    let vm = execute(&code)?;
+    assert_eq!(vm.stack().len(), 1, "{}", p);
+    Ok(vm.tos().unwrap())
+}
+
+#[test]
+fn calls_are_parsed_and_printed() {
+    let e = parse_str("f(1, g(x) + 2) * h()").unwrap();
+    assert_eq!(e.to_string(), "f(1, g(x)+2)*h()");
+    assert_eq!(parse_str(&e.to_string()).unwrap(), e);
+    let p = program(&format!("{} {} gcd(12, fact(3))", FACT, GCD));
+    assert_eq!(p.functions[1].params, ["a", "b"]);
+    assert_eq!(program(&p.to_string()), p);
+} //calls_are_parsed_and_printed
+
+#[test]
+fn syntax_errors_in_programs() {
+    let expected = |s: &str| match parse_program(s) {
+        Err(SyntaxError::Parse(e)) => (e.expected, e.offset),
+        other => panic!("{} gave {:?}", s, other),
+    };
+    assert_eq!(expected("f(x = x; 1"), (Expected::Token(")"), 4));
+    assert_eq!(expected("f(x,) = x; 1"), (Expected::Identifier, 4));
+    assert_eq!(expected("f(x) x; 1"), (Expected::Token("="), 5));
+    assert_eq!(expected("f(x) = x +; 1"), (Expected::Operand, 10));
+    assert_eq!(expected("f(x) = x;"), (Expected::Operand, 9));
+    assert!(parse_str("(1, 2)").is_err());
+    assert!(parse_str("f(1,)").is_err());
+    assert!(parse_str("f(1)(2)").is_err());
+} //syntax_errors_in_programs
+
+#[test]
+fn recursive_functions_evaluate() {
+    let value = |s: &str| eval_program(&program(s), ArithMode::Checked);
+    assert_eq!(value(&format!("{} fact(5)", FACT)), Ok(120));
+    assert_eq!(value(&format!("{} fib(15)", FIB)), Ok(610));
+    assert_eq!(value(&format!("{} sum(10)", SUM)), Ok(55));
+    assert_eq!(value(&format!("{} even(7) * 10 + odd(7)", EVEN)), Ok(1));
+    assert_eq!(value(&format!("{} gcd(84, 36)", GCD)), Ok(12));
+    // a function does not see the variables of its caller
+    let hidden = value("f() = x; let x = 1 in f()");
+    assert_eq!(hidden, Err(EvalError::Unbound("x".into())));
+    assert_eq!(value("g(1)"), Err(EvalError::UnknownFunction("g".into())));
+    let arity = value(&format!("{} gcd(1)", GCD));
+    assert_eq!(arity, Err(EvalError::WrongArity("gcd".into(), 2)));
+    let forever = value("loop(n) = loop(n+1); loop(0)");
+    assert_eq!(forever, Err(EvalError::RecursionLimit));
+    // the deepest calls allowed fit on the stack of a test thread
+    let depth = MAX_CALL_DEPTH as i32;
+    let count = "count(n) = if n == 0 then 0 else let m = n - 1 in 1 + count(m) * 1;";
+    assert_eq!(value(&format!("{} count({})", count, depth - 1)), Ok(depth - 1));
+    assert_eq!(value(&format!("{} count({})", count, depth)), Err(EvalError::RecursionLimit));
+    // a later definition replaces an earlier one
+    assert_eq!(value("f() = 1; f() = 2; f()"), Ok(2));
+} //recursive_functions_evaluate
+
+#[test]
+fn programs_over_other_numbers() {
+    let harmonic = "h(n) = if n == 0 then 0 else 1/n + h(n-1); h(4)";
+    let p: Program<Rational> = parse_number_program(harmonic).unwrap();
+    assert_eq!(evaluate_program(&p), Ok(Rational::new(25, 12).unwrap()));
+    assert_eq!(parse_number_program::<Rational>(&p.to_string()).unwrap(), p);
+    let p: Program<f64> = parse_number_program("area(r) = 3.5 * r^2; area(2)").unwrap();
+    assert_eq!(evaluate_program(&p), Ok(14.0));
+    // the same program over i32 truncates
+    assert_eq!(eval_program(&program(harmonic), ArithMode::Checked), Ok(1));
+    let p: Program<f64> = parse_number_program("f(x) = f(x); f(0.5)").unwrap();
+    assert_eq!(evaluate_program(&p), Err(EvalError::RecursionLimit));
+} //programs_over_other_numbers
+
+#[test]
+fn compiled_functions_agree_with_eval() {
+    let programs = [
+        format!("{} fact(7) - fact(6)", FACT),
+        format!("{} fib(12)", FIB),
+        format!("{} sum(40)", SUM),
+        format!("{} even(9) + 2 * odd(10)", EVEN),
+        format!("{} let a = 91 in gcd(a * 3, 2 * 7 * 13)", GCD),
+        format!("{} {} {} let n = 6 in fact(n) / fib(n) + sum(n)", FACT, FIB, SUM),
+        String::from("f() = 1; f() = 2; f()"),
+        String::from("max(a, b, c) = let m = if a < b then b else a in if m < c then c else m; max(3, 9, 4)"),
+    ];
+    for s in &programs {
+        let p = program(s);
+        let expected = eval_program(&p, ArithMode::Am16).unwrap();
+        for backend in [Backend::Stack, Backend::Registers] {
+            assert_eq!(run(&p, backend), Ok(expected as i16), "{} with {:?}", s, backend);
+        }
+    }
+} //compiled_functions_agree_with_eval
+
+#[test]
+fn compiled_calls_use_call_and_ret() {
+    let p = program(&format!("{} fact(5)", FACT));
+    let listing = csc_7b_fc::am16::listing(&compile_program(&p, Backend::Stack).unwrap());
+    for inst in ["call", "ret", "push bp", "mov sp bp", "mov bp sp", "pop bp"] {
+        assert!(listing.contains(inst), "{} missing from\n{}", inst, listing);
+    }
+} //compiled_calls_use_call_and_ret
+
+#[test]
+fn compile_errors_in_programs() {
+    let error = |s: &str| compile_program(&program(s), Backend::Stack).unwrap_err();
+    assert_eq!(error("f(x) = y; f(1)"), CompileError::Unbound("y".into()));
+    assert_eq!(error("f(x) = x; f(1, 2)"), CompileError::WrongArity("f".into(), 1));
+    assert_eq!(error("f(1)"), CompileError::UnknownFunction("f".into()));
+    let forever = program("loop(n) = loop(n+1); loop(0)");
+    assert_eq!(run(&forever, Backend::Stack), Err(VmError::StackOverflow));
+} //compile_errors_in_programs
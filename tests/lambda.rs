@@ -0,0 +1,108 @@
+//! The untyped lambda calculus: parsing, substitution, the two reduction
+//! strategies and the combinators of the prelude.
+
+use csc_7b_fc::lambda::*;
+
+fn term(s: &str) -> Term {
+    parse_term(s).unwrap()
+}
+
+#[test]
+fn terms_parse_and_print() {
+    let t = term("\\x y.x (y x) \\z.z");
+    assert_eq!(t.to_string(), "\\x y.x (y x) (\\z.z)");
+    assert_eq!(term(&t.to_string()), t);
+    assert_eq!(term("λf.f f"), term("\\f.(f f)"));
+    // λ ends the identifier before it
+    assert_eq!(term("\\x.xλy.y"), term("\\x.x (\\y.y)"));
+    assert_eq!(term("λx.λy.xλz.z"), term("\\x y.x (\\z.z)"));
+    assert_eq!(term("(\\x.x) a b").to_string(), "(\\x.x) a b");
+    assert_eq!(term("f (g a)").to_string(), "f (g a)");
+    let error = |s: &str| match parse_term(s) {
+        Err(LambdaError::Syntax { offset, expected }) => (offset, expected),
+        other => panic!("{} gave {:?}", s, other),
+    };
+    assert_eq!(error("\\.x"), (1, "a variable"));
+    assert_eq!(error("\\x x"), (4, "'.'"));
+    assert_eq!(error("(x y"), (4, "')'"));
+    assert_eq!(error("x )"), (2, "end of input"));
+} //terms_parse_and_print
+
+#[test]
+fn substitution_avoids_capture() {
+    // the y of the argument must stay free
+    let t = normalize(&term("(\\x y.x) y"), Strategy::NormalOrder, 10).unwrap();
+    assert_eq!(t, term("\\y1.y"));
+    let t = term("\\y.x y1 y").subst("x", &term("y"));
+    assert_eq!(t, term("\\y2.y y1 y2"));
+    // bound occurrences are not replaced
+    assert_eq!(term("\\x.x").subst("x", &term("z")), term("\\x.x"));
+    assert_eq!(term("(\\x.x) x").free_vars().into_iter().collect::<Vec<_>>(), ["x"]);
+} //substitution_avoids_capture
+
+#[test]
+fn strategies_differ_on_unused_loops() {
+    let prelude = Prelude::standard();
+    let omega = "((\\x.x x) (\\x.x x))";
+    let t = prelude.parse(&format!("K I {}", omega)).unwrap();
+    assert_eq!(normalize(&t, Strategy::NormalOrder, 100), Ok(term("\\x.x")));
+    assert_eq!(normalize(&t, Strategy::CallByValue, 100), Err(LambdaError::StepLimit(100)));
+    // call-by-value does not reduce inside lambdas
+    let t = term("\\x.(\\y.y) x");
+    assert_eq!(step(&t, Strategy::CallByValue), None);
+    assert_eq!(step(&t, Strategy::NormalOrder), Some(term("\\x.x")));
+} //strategies_differ_on_unused_loops
+
+// Bound names of normal forms depend on the renaming done by substitution,
+// so booleans are compared by applying them to free variables a and b,
+// and numerals by applying them to f and x.
+fn boolean(prelude: &Prelude, s: &str) -> bool {
+    match prelude.eval(&format!("({}) a b", s)) {
+        Ok(Term::Var(x)) if x == "a" || x == "b" => x == "a",
+        other => panic!("{} is not a boolean: {:?}", s, other),
+    }
+}
+
+fn numeral(prelude: &Prelude, s: &str) -> Term {
+    prelude.eval(&format!("({}) f x", s)).unwrap()
+}
+
+// f (f ... (f x)) with n applications
+fn applied(n: usize) -> Term {
+    (0..n).fold(Term::var("x"), |t, _| Term::app(Term::var("f"), t))
+}
+
+#[test]
+fn prelude_booleans_and_pairs() {
+    let prelude = Prelude::standard();
+    let eval = |s: &str| prelude.eval(s).unwrap();
+    let truth = |s: &str| boolean(&prelude, s);
+    assert_eq!(eval("TRUE"), term("\\x y.x"));
+    assert!(truth("TRUE") && !truth("FALSE"));
+    assert!(!truth("AND TRUE FALSE") && truth("AND TRUE TRUE"));
+    assert!(truth("OR FALSE TRUE") && !truth("OR FALSE FALSE"));
+    assert!(truth("NOT FALSE") && !truth("NOT TRUE"));
+    assert_eq!(eval("IFELSE TRUE c d"), term("c"));
+    assert_eq!(eval("IFELSE0 FALSE c d"), term("d"));
+    assert_eq!(eval("CAR (CDR (CONS c (CONS d e)))"), term("d"));
+    assert!(truth("ISNIL NIL") && !truth("ISNIL (CONS c d)"));
+    assert_eq!(eval("S K K y"), term("y"));
+} //prelude_booleans_and_pairs
+
+#[test]
+fn prelude_church_numerals() {
+    let prelude = Prelude::standard();
+    let value = |s: &str| numeral(&prelude, s);
+    assert_eq!(prelude.eval("church3").unwrap(), church(3));
+    assert_eq!(value("ZERO"), applied(0));
+    assert_eq!(value("SUCC church3"), applied(4));
+    assert_eq!(value("ADD church2 church3"), applied(5));
+    assert_eq!(value("TIMES church2 church3"), applied(6));
+    assert_eq!(value("EXPT church2 church3"), applied(8));
+    assert_eq!(value("PRED church3"), applied(2));
+    assert_eq!(value("SUBTRACT church3 ONE"), applied(2));
+    assert!(boolean(&prelude, "ISZERO (SUBTRACT church2 church3)"));
+    let fact = "FIX (\\g n.IFELSE (ISZERO n) ONE (TIMES n (g (PRED n)))) church3";
+    assert_eq!(value(fact), applied(6));
+    assert!(prelude.names().any(|x| x == "FIX"));
+} //prelude_church_numerals
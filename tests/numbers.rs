@@ -0,0 +1,98 @@
+//! The expression pipeline over other types of numbers: f64 with decimal
+//! literals, exact rationals, and the AM16 backend rejecting non-integers.
+
+use csc_7b_fc::compiler::{compile_number, CompileError};
+use csc_7b_fc::number::{evaluate, Number, Rational};
+use csc_7b_fc::vm16::execute;
+use csc_7b_fc::{eval_mode, parse_number, parse_str, ArithMode, Backend, EvalError, Expr};
+use csc_7b_fc::{Expected, LexErrorKind, SyntaxError};
+
+fn value<N: Number>(s: &str) -> Result<N, EvalError> {
+    evaluate(&parse_number::<N>(s).unwrap())
+}
+
+fn ratio(num: i64, den: i64) -> Rational {
+    Rational::new(num, den).unwrap()
+}
+
+#[test]
+fn floating_point_has_decimal_literals() {
+    assert_eq!(value::<f64>("3.25 * 2"), Ok(6.5));
+    assert_eq!(value::<f64>("7 / 2"), Ok(3.5));
+    assert_eq!(value::<f64>("2 ^ -1 + 4 ^ 0.5"), Ok(2.5));
+    assert_eq!(value::<f64>("let r = 1.5 in if r < 2 then r else 0"), Ok(1.5));
+    assert_eq!(value::<f64>("1 / 0.0"), Err(EvalError::DivideByZero));
+    let e: Expr<f64> = parse_number("-0.5*x").unwrap();
+    assert_eq!(e.to_string(), "-0.5*x");
+} //floating_point_has_decimal_literals
+
+#[test]
+fn rational_division_is_exact() {
+    assert_eq!(value::<Rational>("1/3 + 1/6"), Ok(ratio(1, 2)));
+    assert_eq!(value::<Rational>("0.1 + 0.2 == 0.3"), Ok(ratio(1, 1)));
+    assert_eq!(value::<Rational>("19.99 * 3"), Ok(ratio(5997, 100)));
+    assert_eq!(value::<Rational>("(2/3)^-2"), Ok(ratio(9, 4)));
+    assert_eq!(value::<Rational>("7/2 % 1"), Ok(ratio(1, 2)));
+    assert_eq!(value::<Rational>("4^(1/2)"), Err(EvalError::NotAnInteger));
+    assert_eq!(value::<Rational>("1/(1-1)"), Err(EvalError::DivideByZero));
+    assert_eq!(ratio(6, -4).to_string(), "-3/2");
+    // a fraction prints as a division, which evaluates back to it
+    let half = Expr::Pow(Box::new(Expr::Var("x".into())), Box::new(Expr::Val(ratio(1, 2))));
+    assert_eq!(half.to_string(), "x^(1/2)");
+} //rational_division_is_exact
+
+#[test]
+fn integers_reject_decimal_literals() {
+    match parse_str("1 + 2.5") {
+        Err(SyntaxError::Parse(e)) => {
+            assert_eq!((e.offset, e.expected), (4, Expected::Integer));
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(value::<i32>("7 / 2"), Ok(3));
+} //integers_reject_decimal_literals
+
+#[test]
+fn literals_are_read_with_the_number_type() {
+    // integers too large for i32 are fine for wider types
+    assert_eq!(value::<Rational>("10000000000 / 3"), Ok(ratio(10_000_000_000, 3)));
+    assert_eq!(value::<f64>("10000000000 / 4"), Ok(2.5e9));
+    assert_eq!(value::<i32>("2147483647"), Ok(i32::MAX));
+    let lex_error = |e: Result<Expr<Rational>, SyntaxError>| match e {
+        Err(SyntaxError::Lex(e)) => e.kind,
+        other => panic!("{:?}", other),
+    };
+    let too_large = "9223372036854775808";
+    assert_eq!(lex_error(parse_number(too_large)), LexErrorKind::Overflow(too_large.into()));
+    assert!(matches!(parse_number::<i32>("2147483648"), Err(SyntaxError::Lex(_))));
+    // trailing zeros do not count, but 39 significant digits are too many
+    let half = format!("0.5{}", "0".repeat(60));
+    assert_eq!(value::<Rational>(&half), Ok(ratio(1, 2)));
+    let precise = format!("0.{}1", "0".repeat(38));
+    assert_eq!(lex_error(parse_number(&precise)), LexErrorKind::Precision(precise.clone()));
+    // i32 still reports a decimal as a misplaced non-integer
+    match parse_number::<i32>(&precise) {
+        Err(SyntaxError::Parse(e)) => assert_eq!(e.expected, Expected::Integer),
+        other => panic!("{:?}", other),
+    }
+} //literals_are_read_with_the_number_type
+
+#[test]
+fn integers_evaluate_like_checked_mode() {
+    // evaluate and eval_mode share their evaluator
+    for s in ["7 / 2", "-7 % 3", "2^31", "let x = 3 in x*x < 10", "1 && 0 || !0", "5/0"] {
+        let e = parse_str(s).unwrap();
+        assert_eq!(evaluate(&e), eval_mode(&e, ArithMode::Checked), "{}", s);
+    }
+} //integers_evaluate_like_checked_mode
+
+#[test]
+fn am16_only_compiles_integers() {
+    let e: Expr<Rational> = parse_number("let x = 4.0 in x * 3").unwrap();
+    let code = compile_number(&e, Backend::Stack).unwrap();
+    assert_eq!(execute(&code).unwrap().tos(), Some(12));
+    let e: Expr<f64> = parse_number("1 + 2.5").unwrap();
+    let err = compile_number(&e, Backend::Registers).unwrap_err();
+    assert_eq!(err, CompileError::NotAnInteger(String::from("2.5")));
+    assert_eq!(err.to_string(), "constant 2.5 is not an integer, AM16 only has integers");
+} //am16_only_compiles_integers
@@ -0,0 +1,42 @@
+//! Parsing with the operator table: the right-associative `^` of the
+//! default configuration, and operators registered in a [ParserConfig].
+
+use csc_7b_fc::Expr::{self, *};
+use csc_7b_fc::{eval, parse_str, parse_str_with, Assoc, BinaryOp, ParserConfig, PrefixOp};
+
+fn value(s: &str) -> Option<i32> {
+    parse_str(s).ok().and_then(|e| eval(&e))
+}
+
+#[test]
+fn power_is_right_associative() {
+    assert_eq!(value("2^3^2"), Some(512));
+    assert_eq!(value("(2^3)^2"), Some(64));
+    assert_eq!(value("2*3^2+1"), Some(19));
+    // unary minus binds looser than ^, except in the exponent
+    assert_eq!(value("-2^2"), Some(-4));
+    assert_eq!(value("(-2)^2"), Some(4));
+    assert_eq!(value("2^-1"), Some(0));
+    assert_eq!(value("0^-1"), None);
+    let e = parse_str("2^3^2").unwrap();
+    assert_eq!(e.to_string(), "2^3^2");
+    assert_eq!(parse_str("(2^3)^2").unwrap().to_string(), "(2^3)^2");
+} //power_is_right_associative
+
+#[test]
+fn operators_can_be_registered() {
+    let mut config: ParserConfig = ParserConfig::default();
+    config
+        .add_binary(BinaryOp { symbol: "**", precedence: 230, assoc: Assoc::Right, build: Pow })
+        .add_binary(BinaryOp { symbol: "+", precedence: 200, assoc: Assoc::Left, build: Plus })
+        .add_prefix(PrefixOp { symbol: "~", precedence: 220, build: Not });
+    let parse = |s: &str| parse_str_with(s, &config).map(|e| e.to_string()).ok();
+    assert_eq!(parse("2**3**2"), Some(String::from("2^3^2")));
+    assert_eq!(parse("1+2*3"), Some(String::from("(1+2)*3")));
+    assert_eq!(parse("~1 < 2"), Some(String::from("!1<2")));
+    // the symbols of the default configuration are not known to an empty one
+    let empty = ParserConfig::empty();
+    assert!(parse_str_with("1+2", &empty).is_err());
+    let two: Expr = parse_str_with("(2)", &empty).unwrap();
+    assert_eq!(two, Val(2));
+} //operators_can_be_registered
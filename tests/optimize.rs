@@ -0,0 +1,91 @@
+//! The simplification of expressions before compilation: what it removes,
+//! what it must leave for run time, and that values do not change.
+
+mod common;
+
+use std::ops::RangeInclusive;
+
+use common::{constant, random_expr, Rng, MODES};
+use csc_7b_fc::optimize::{simplify, size};
+use csc_7b_fc::ArithMode::{self, *};
+use csc_7b_fc::{compile, eval_mode, parse_str, Expr};
+
+// the simplified expression, printed, and the number of nodes removed
+fn simplified(s: &str, mode: ArithMode) -> (String, usize) {
+    let mut e = parse_str(s).unwrap();
+    let before = size(&e);
+    let removed = simplify(&mut e, mode);
+    assert_eq!(size(&e) + removed, before);
+    (e.to_string(), removed)
+}
+
+#[test]
+fn constants_and_identities_are_removed() {
+    let am16 = |s: &str| simplified(s, Am16);
+    assert_eq!(am16("1+2*3"), ("7".into(), 4));
+    assert_eq!(am16("let x = 5 in x*1+0"), ("let x = 5 in x".into(), 4));
+    assert_eq!(am16("let x = 5 in 0+1*x*(4-4)"), ("let x = 5 in 0".into(), 8));
+    assert_eq!(am16("let y = 2 in --y"), ("let y = 2 in y".into(), 2));
+    let branch = "let x = 1 in if x then 2*3 else 4+0";
+    assert_eq!(am16(branch), ("let x = 1 in if x then 6 else 4".into(), 4));
+    assert_eq!(am16("if 1 < 2 then 10 else 1/0"), ("10".into(), 7));
+    // x - c is x + (-c) when -c is a shorter constant
+    assert_eq!(am16("let x = 1 in x - (0-5)"), ("let x = 1 in x+5".into(), 2));
+    assert_eq!(am16("let x = 1 in x - 5"), ("let x = 1 in x-5".into(), 0));
+    assert_eq!(am16("let x = 1 in x - 32767 - 1").1, 0);
+} //constants_and_identities_are_removed
+
+#[test]
+fn runtime_errors_are_not_folded() {
+    for mode in MODES {
+        assert_eq!(simplified("1/0", mode), ("1/0".into(), 0));
+        assert_eq!(simplified("2*3 + 7%(2-2)", mode), ("6+7%0".into(), 4));
+        assert_eq!(simplified("(1/0)*0", mode).1, 0);
+        assert_eq!(simplified("0^(0-1)", mode), ("0^(-1)".into(), 2));
+    }
+    // overflow is an error of checked arithmetic only
+    assert_eq!(simplified("(2147483647+1)*0", Checked).1, 0);
+    assert_eq!(simplified("(2147483647+1)*0", Wrapping), ("0".into(), 4));
+    // -(-x) overflows in checked mode when x is i32::MIN
+    assert_eq!(simplified("let x = 1 in --x", Checked).1, 0);
+    assert_eq!(simplified("let x = 1 in --x", Saturating).1, 0);
+    assert_eq!(simplified("let x = 1 in --x", Wrapping).1, 2);
+    // constants that do not fit AM16 are still rejected by the compiler
+    for s in ["70000+0", "70000*0", "70000-70000", "1 || 70000", "if 0 then 70000 else 1"] {
+        let mut e = parse_str(s).unwrap();
+        let error = compile(&e).unwrap_err();
+        simplify(&mut e, Am16);
+        assert_eq!(compile(&e).unwrap_err(), error, "{}", s);
+    }
+} //runtime_errors_are_not_folded
+
+// small and 16-bit constants, and the edges of both ranges
+const CONSTANTS: [RangeInclusive<i32>; 7] = [
+    0..=2,
+    -30..=269,
+    -35000..=34999,
+    i32::MIN..=i32::MIN,
+    i32::MAX..=i32::MAX,
+    -1..=-1,
+    65536..=65536,
+];
+
+#[test]
+fn simplification_keeps_the_values() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..3000 {
+        let depth = 1 + rng.below(6) as u32;
+        let e: Expr = random_expr(&mut rng, depth, |r| constant(r, &CONSTANTS), &mut Vec::new());
+        for mode in MODES {
+            let mut s = e.clone();
+            simplify(&mut s, mode);
+            let (before, after) = (eval_mode(&e, mode), eval_mode(&s, mode));
+            assert_eq!(after, before, "{} became {} in {:?}", e, s, mode);
+        }
+        let mut s = e.clone();
+        simplify(&mut s, Am16);
+        if let Err(err) = compile(&e) {
+            assert_eq!(compile(&s).err(), Some(err), "{} became {}", e, s);
+        }
+    }
+} //simplification_keeps_the_values
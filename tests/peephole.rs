@@ -0,0 +1,68 @@
+//! The peephole optimizer, checked with its VM harness: optimized programs
+//! must end with the same registers and stack as the original ones.
+
+mod common;
+
+use std::ops::RangeInclusive;
+
+use common::{constant, random_expr, Rng};
+use csc_7b_fc::am16::Instr::*;
+use csc_7b_fc::am16::Operand::{Imm, Reg as R};
+use csc_7b_fc::am16::Reg::*;
+use csc_7b_fc::compiler::compile_program;
+use csc_7b_fc::peephole::{optimize, outcome, verify};
+use csc_7b_fc::program::parse_program;
+use csc_7b_fc::vm16::VmError;
+use csc_7b_fc::{compile_with, Backend};
+
+#[test]
+fn pairs_are_merged() {
+    let code = [Push(Imm(3)), Pop(Ax), Push(R(Bx)), Pop(Bx), Push(R(Ax)), Pop(Cx), Push(R(Cx))];
+    let optimized = optimize(&code);
+    assert_eq!(optimized, [Mov(Imm(3), Ax), Mov(R(Ax), Cx), Push(R(Cx))]);
+    assert_eq!(verify(&code, &optimized), Ok(()));
+    // immediates of mov only go up to 255
+    assert_eq!(optimize(&[Push(Imm(256)), Pop(Ax)]), [Push(Imm(256)), Pop(Ax)]);
+    // pop sp corrupts the stack pointer, which the optimizer must not hide
+    let code = [Push(R(Sp)), Pop(Sp)];
+    assert_eq!(optimize(&code), code);
+    assert_eq!(outcome(&code), Err(VmError::StackPointerCorruption));
+    assert_eq!(optimize(&[Push(R(Ax)), Pop(Sp)]), [Push(R(Ax)), Pop(Sp)]);
+} //pairs_are_merged
+
+#[test]
+fn branch_targets_are_kept_and_renumbered() {
+    // 1: push 1, 2: pop cx, 3: jz 6, 4: push 7, 5: pop ax, 6: push ax
+    let code = [Push(Imm(1)), Pop(Cx), Jz(6), Push(Imm(7)), Pop(Ax), Push(R(Ax))];
+    let optimized = optimize(&code);
+    assert_eq!(optimized, [Mov(Imm(1), Cx), Jz(4), Mov(Imm(7), Ax), Push(R(Ax))]);
+    assert_eq!(verify(&code, &optimized), Ok(()));
+    // the target of a branch cannot be merged with the instruction before
+    let code = [Jmp(3), Push(R(Bx)), Pop(Bx), Push(R(Bx))];
+    assert_eq!(optimize(&code), code);
+} //branch_targets_are_kept_and_renumbered
+
+// small numbers, push immediates and 16-bit constants
+const CONSTANTS: [RangeInclusive<i32>; 3] = [0..=3, 0..=2047, -32768..=32767];
+
+#[test]
+fn optimized_programs_verify() {
+    let mut rng = Rng(0xd1b5_4a32_d192_ed03);
+    for _ in 0..2000 {
+        let depth = 1 + rng.below(6) as u32;
+        let e = random_expr(&mut rng, depth, |r| constant(r, &CONSTANTS), &mut Vec::new());
+        for backend in [Backend::Stack, Backend::Registers] {
+            let code = compile_with(&e, backend).unwrap();
+            let optimized = optimize(&code);
+            assert!(optimized.len() <= code.len());
+            if let Err(mismatch) = verify(&code, &optimized) {
+                panic!("{} with {:?}:\n{}", e, backend, mismatch);
+            }
+        }
+    }
+    let fact = "fact(n) = if n == 0 then 1 else n * fact(n-1); fact(7) - fact(3)";
+    let code = compile_program(&parse_program(fact).unwrap(), Backend::Stack).unwrap();
+    let optimized = optimize(&code);
+    assert!(optimized.len() < code.len());
+    assert_eq!(verify(&code, &optimized), Ok(()));
+} //optimized_programs_verify
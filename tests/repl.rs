@@ -0,0 +1,43 @@
+//! REPL sessions: bindings persist across lines and errors do not end
+//! the session.
+
+use csc_7b_fc::repl::{ReplError, Session};
+
+#[test]
+fn bindings_persist_across_lines() {
+    let mut s = Session::new();
+    assert_eq!(s.line(":let x = 6*7").unwrap(), "x = 42");
+    assert_eq!(s.line(":let y = x - 2").unwrap(), "y = 40");
+    assert_eq!(s.line("x + y").unwrap(), "82");
+    assert_eq!(s.line(":ast y^2").unwrap(), "Pow(Var(\"y\"), Val(2))");
+    // the compiled code sees the bindings too
+    let trace = s.line(":run x / 2").unwrap();
+    assert!(trace.ends_with("result: 21"), "{}", trace);
+    assert!(s.line(":asm z").is_err());
+} //bindings_persist_across_lines
+
+#[test]
+fn errors_do_not_end_the_session() {
+    let mut s = Session::new();
+    assert!(matches!(s.line("3 +"), Err(ReplError::Syntax(_))));
+    assert!(matches!(s.line("1/0"), Err(ReplError::Eval(_))));
+    assert!(matches!(s.line(":let 3 = 4"), Err(ReplError::BadBinding)));
+    assert!(matches!(s.line(":frobnicate"), Err(ReplError::UnknownCommand(_))));
+    let trace = s.line(":run 1/0").unwrap();
+    assert!(trace.ends_with("VM error: Division by Zero"), "{}", trace);
+    assert_eq!(s.line("  ").unwrap(), "");
+    assert_eq!(s.line("2*3").unwrap(), "6");
+    assert_eq!(s.history().len(), 6);
+    assert!(s.line(":history").unwrap().ends_with("7  :history"));
+} //errors_do_not_end_the_session
+
+#[test]
+fn quit_ends_the_session() {
+    for quit in [":quit", ":q"] {
+        let mut s = Session::new();
+        assert_eq!(s.line("1+1").unwrap(), "2");
+        assert!(!s.finished());
+        assert_eq!(s.line(quit).unwrap(), "");
+        assert!(s.finished());
+    }
+} //quit_ends_the_session
@@ -0,0 +1,27 @@
+//! Property test of the `Display` impl of `Expr`: printing a random tree
+//! and parsing the result must give back the same tree.  Constants are
+//! not negative, since the parser reads `-3` as `Neg(Val(3))`.
+
+mod common;
+
+use std::ops::RangeInclusive;
+
+use common::{constant, random_expr, Rng};
+use csc_7b_fc::parse_str;
+
+// one digit or several
+const CONSTANTS: [RangeInclusive<i32>; 2] = [0..=9, 0..=99999];
+
+#[test]
+fn display_output_parses_to_same_tree() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..5000 {
+        let depth = 1 + rng.below(7) as u32;
+        let e = random_expr(&mut rng, depth, |r| constant(r, &CONSTANTS), &mut Vec::new());
+        let printed = e.to_string();
+        match parse_str(&printed) {
+            Ok(back) => assert_eq!(back, e, "{} does not parse back to the same tree", printed),
+            Err(err) => panic!("{} does not parse: {}", printed, err),
+        }
+    }
+} //display_output_parses_to_same_tree
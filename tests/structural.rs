@@ -0,0 +1,54 @@
+//! Structural equality, hashing and cloning of `Expr`, including trees
+//! deep enough to overflow the stack if these were recursive.
+
+use csc_7b_fc::parse_str;
+use csc_7b_fc::Expr::{self, *};
+use std::collections::HashSet;
+
+// 1+2+...+n nested to the left
+fn long_sum(n: i32) -> Expr {
+    let mut e = Val(1);
+    for i in 2..=n {
+        e = Plus(Box::new(e), Box::new(Val(i)));
+    }
+    e
+} //long_sum
+
+#[test]
+fn equal_trees_have_equal_hashes() {
+    let a = parse_str("let x = 2 in if x < 3 then -x*4 else !x").unwrap();
+    let b = parse_str("let x = 2 in if x<3 then (-x)*4 else !x").unwrap();
+    let c = parse_str("let y = 2 in if y < 3 then -y*4 else !y").unwrap();
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_ne!(parse_str("1-2-3").unwrap(), parse_str("1-(2-3)").unwrap());
+    let set: HashSet<Expr> = [a.clone(), b, c.clone(), a].into_iter().collect();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(&c));
+} //equal_trees_have_equal_hashes
+
+#[test]
+fn clone_is_deep() {
+    let a = parse_str("(1+2)*let x = 3 in x%2").unwrap();
+    let mut b = a.clone();
+    if let Times(_, y) = &mut b {
+        **y = Val(0);
+    }
+    assert_eq!(a.to_string(), "(1+2)*(let x = 3 in x%2)");
+    assert_eq!(b.to_string(), "(1+2)*0");
+} //clone_is_deep
+
+#[test]
+fn deep_trees_do_not_overflow() {
+    let e = long_sum(100_000);
+    assert_eq!(e.iter().count(), 199_999);
+    let copy = e.clone();
+    assert_eq!(copy, e);
+    let mut other = long_sum(100_000);
+    if let Plus(_, last) = &mut other {
+        **last = Val(0);
+    }
+    assert_ne!(other, e);
+    let set: HashSet<Expr> = [e, copy, other].into_iter().collect();
+    assert_eq!(set.len(), 2);
+} //deep_trees_do_not_overflow
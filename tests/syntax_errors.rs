@@ -0,0 +1,51 @@
+//! Errors of the lexer and the parser, located in the input.
+
+use csc_7b_fc::{parse_str, tokenize, Expected, LexErrorKind, SyntaxError, Token};
+
+// the span, the offending token and what was expected by the parser
+fn parse_error(s: &str) -> ((usize, usize), Token, Expected) {
+    match parse_str(s) {
+        Err(SyntaxError::Parse(e)) => ((e.offset, e.end), e.found, e.expected),
+        other => panic!("{:?} gave {:?}", s, other),
+    }
+}
+
+#[test]
+fn parse_errors_point_at_the_offending_token() {
+    // an unmatched ( is reported where it is
+    assert_eq!(parse_error("1+(2*3"), ((2, 3), Token::Sym('('), Expected::ClosingParen));
+    assert_eq!(parse_error("((1)").0, (0, 1));
+    assert_eq!(parse_error("(1))").2, Expected::Operator);
+    // a dangling operator needs an operand, at the end or before the next
+    // operator
+    assert_eq!(parse_error("1+"), ((2, 2), Token::EOF, Expected::Operand));
+    assert_eq!(parse_error("1 + * 2"), ((4, 5), Token::Sym('*'), Expected::Operand));
+    // empty input ends before the first operand
+    assert_eq!(parse_error(""), ((0, 0), Token::EOF, Expected::Operand));
+    assert_eq!(parse_error("  ").0, (2, 2));
+    let e = parse_str("1+(2*3").unwrap_err();
+    assert_eq!(e.span(), (2, 3));
+    let message = "parse error at offset 2: expected a matching ')', found unmatched '('";
+    assert_eq!(e.to_string(), message);
+} //parse_errors_point_at_the_offending_token
+
+#[test]
+fn lexical_errors_span_the_offending_text() {
+    let lex_error = |s: &str| match parse_str(s) {
+        Err(SyntaxError::Lex(e)) => ((e.start, e.end), e.kind),
+        other => panic!("{:?} gave {:?}", s, other),
+    };
+    assert_eq!(lex_error("1 + @"), ((4, 5), LexErrorKind::UnknownChar('@')));
+    assert_eq!(lex_error("x#"), ((1, 2), LexErrorKind::UnknownChar('#')));
+    // spans count characters, not bytes
+    assert_eq!(lex_error("é+$"), ((2, 3), LexErrorKind::UnknownChar('$')));
+    let too_large = lex_error("1 + 2147483648 * 2");
+    assert_eq!(too_large, ((4, 14), LexErrorKind::Overflow("2147483648".into())));
+    assert!(parse_str("2147483647").is_ok());
+    // tabs and newlines are whitespace, and the error is the first one
+    assert_eq!(lex_error("1\t+\n99999999999 @").0, (4, 15));
+    let e = tokenize("1 ~ 2").unwrap_err();
+    assert_eq!(e.to_string(), "lexical error at offset 2: unknown character '~'");
+    // commas and semicolons are tokens, for function calls and definitions
+    assert!(tokenize("f(1, 2); g").is_ok());
+} //lexical_errors_span_the_offending_text
@@ -0,0 +1,83 @@
+//! Hindley-Milner inference of the types of lambda terms, which must agree
+//! with the types F# infers for the definitions of `lambdacalc.fsx`.
+
+use csc_7b_fc::lambda::*;
+use csc_7b_fc::types::*;
+
+fn type_of(s: &str) -> Result<String, TypeError> {
+    Ok(infer(&parse_term(s).unwrap())?.to_string())
+}
+
+#[test]
+fn prelude_types_agree_with_fsharp() {
+    let env = TypeEnv::standard();
+    let ty = |x: &str| env.get(x).unwrap().ty.to_string();
+    // the types printed by F#, without the names of the parameters
+    assert_eq!(ty("I"), "'a -> 'a");
+    assert_eq!(ty("K"), "'a -> 'b -> 'a");
+    assert_eq!(ty("S"), "('a -> 'b -> 'c) -> ('a -> 'b) -> 'a -> 'c");
+    assert_eq!(ty("FALSE"), "'a -> 'b -> 'b");
+    assert_eq!(ty("ZERO"), "'a -> 'b -> 'b");
+    assert_eq!(ty("IFELSE0"), "('a -> 'b -> 'c) -> 'a -> 'b -> 'c");
+    assert_eq!(ty("CONS"), "'a -> 'b -> ('a -> 'b -> 'c) -> 'c");
+    assert_eq!(ty("ISNIL"), "(('a -> 'b -> 'c -> 'd -> 'e -> 'e) -> ('f -> 'g -> 'f) -> 'h) -> 'h");
+    assert_eq!(ty("ADD"), "('a -> 'b -> 'c) -> ('a -> 'd -> 'b) -> 'a -> 'd -> 'c");
+    assert_eq!(ty("TIMES"), "('a -> 'b -> 'c) -> ('d -> 'a) -> 'd -> 'b -> 'c");
+    assert_eq!(ty("FIX"), "('a -> 'a) -> 'a");
+    let listing = env.to_string();
+    assert!(listing.starts_with("val I : 'a -> 'a\nval K : 'a -> 'b -> 'a\n"), "{}", listing);
+    assert_eq!(listing.lines().count(), PRELUDE.len());
+    // the prelude definitions are polymorphic
+    let t = parse_term("CONS (church2 I) (church3 (K I) I)").unwrap();
+    assert!(env.infer(&t).is_ok());
+} //prelude_types_agree_with_fsharp
+
+#[test]
+fn let_is_polymorphic_but_lambda_is_not() {
+    assert_eq!(type_of("let i = \\x.x in i i"), Ok("'a -> 'a".into()));
+    assert!(type_of("(\\i.i i) (\\x.x)").is_err());
+    assert_eq!(type_of("let k = \\x y.x in k (k a)"), Err(TypeError::Unbound("a".into())));
+    // a let does not generalize the variables of enclosing lambdas
+    assert!(type_of("\\f.let g = f in g g").is_err());
+    assert_eq!(type_of("\\f.let g = \\x.f x in g"), Ok("('a -> 'b) -> 'a -> 'b".into()));
+} //let_is_polymorphic_but_lambda_is_not
+
+#[test]
+fn let_works_with_defined_names() {
+    // the definitions of an environment were typed by another inference
+    let env = TypeEnv::standard();
+    let ty = |env: &TypeEnv, s: &str| env.infer(&parse_term(s).unwrap()).map(|t| t.to_string());
+    assert_eq!(ty(&env, "let x = I in x"), Ok("'a -> 'a".into()));
+    assert_eq!(ty(&env, "\\y.let z = y in z"), Ok("'a -> 'a".into()));
+    assert_eq!(ty(&env, "let k = K in k (k I)"), Ok("'a -> 'b -> 'c -> 'c".into()));
+    assert!(ty(&env, "\\f.let g = f in g g").is_err());
+    let mut env = TypeEnv::new();
+    env.define("ID", "\\x.x").unwrap();
+    let t = env.define("SELF", "let i = ID in i i").unwrap();
+    assert_eq!(t.to_string(), "'a -> 'a");
+    assert_eq!(ty(&env, "\\y.let z = SELF y in z"), Ok("'a -> 'a".into()));
+} //let_works_with_defined_names
+
+#[test]
+fn errors_report_both_types() {
+    let e = type_of("\\x.x x").unwrap_err();
+    assert_eq!(e.to_string(), "cannot unify 'a with 'a -> 'b");
+    let mut env = TypeEnv::standard();
+    assert!(matches!(env.define("OMEGA", "\\x.x x"), Err(TypeError::Mismatch(_, _))));
+    assert!(matches!(env.define("BAD", "\\x."), Err(TypeError::Syntax(_))));
+    assert!(env.get("OMEGA").is_none());
+    assert_eq!(env.define("TWICE", "\\f x.f (f x)").unwrap().to_string(), "('a -> 'a) -> 'a -> 'a");
+} //errors_report_both_types
+
+#[test]
+fn let_terms_parse_and_reduce() {
+    let t = parse_term("let i = \\x.x in i i y").unwrap();
+    assert_eq!(t.to_string(), "let i = \\x.x in i i y");
+    assert_eq!(parse_term(&t.to_string()).unwrap(), t);
+    assert_eq!(parse_term("f (let x = a in x)").unwrap().to_string(), "f (let x = a in x)");
+    for strategy in [Strategy::NormalOrder, Strategy::CallByValue] {
+        assert_eq!(normalize(&t, strategy, 10), Ok(Term::var("y")));
+    }
+    assert!(parse_term("let x a in x").is_err());
+    assert!(parse_term("let in = a in x").is_err());
+} //let_terms_parse_and_reduce
@@ -0,0 +1,58 @@
+//! The AM16 machine on small hand-written programs.
+
+use csc_7b_fc::am16::Instr::*;
+use csc_7b_fc::am16::Operand::{Imm, Reg as R};
+use csc_7b_fc::am16::Reg::*;
+use csc_7b_fc::vm16::*;
+
+#[test]
+fn arithmetic_wraps_around_16_bits() {
+    let code = [
+        Mov(Imm(255), Ax),
+        Mult(Imm(129), Ax), // 32895 wraps to -32641
+        Mov(R(Ax), Bx),
+        Sub(Imm(200), Bx), // -32841 wraps to 32695
+        Mov(Imm(0), Cx),
+        Sub(Imm(1), Cx),
+        Div(Imm(2), Cx), // -1/2 is 0, remainder -1
+        Push(R(Ax)),
+    ];
+    let vm = execute(&code).unwrap();
+    assert_eq!(vm.regs.ax, -32641);
+    assert_eq!(vm.regs.bx, 32695);
+    assert_eq!(vm.regs.cx, -1);
+    assert_eq!(vm.stack(), [-32641]);
+    // div puts the quotient in dst and the remainder in cx
+    let vm = execute(&[Mov(Imm(17), Ax), Mov(Imm(5), Bx), Div(R(Bx), Ax)]).unwrap();
+    assert_eq!((vm.regs.ax, vm.regs.cx), (3, 2));
+} //arithmetic_wraps_around_16_bits
+
+#[test]
+fn runtime_errors() {
+    let error = |code: &[_]| execute(code).err();
+    assert_eq!(error(&[Mov(Imm(1), Ax), Div(R(Bx), Ax)]), Some(VmError::DivideByZero));
+    assert_eq!(error(&[Push(Imm(1)), Pop(Sp)]), Some(VmError::StackPointerCorruption));
+    assert_eq!(error(&[Pop(Ax)]), Some(VmError::StackUnderflow));
+    let mut vm = Vm::new();
+    vm.load(&[Jmp(1)]).unwrap();
+    vm.max_steps = 50;
+    assert_eq!(vm.run(), Err(VmError::StepLimit(50)));
+    assert_eq!(vm.steps(), 50);
+    assert_eq!(error(&[Push(Imm(0)), Jmp(1)]), Some(VmError::StackOverflow));
+} //runtime_errors
+
+#[test]
+fn call_and_ret() {
+    // 1: call 4, 2: push ax, 3: jmp 7, 4: mov 7 ax, 5: add 1 ax, 6: ret
+    let code = [Call(4), Push(R(Ax)), Jmp(7), Mov(Imm(7), Ax), Add(Imm(1), Ax), Ret];
+    let mut vm = Vm::new();
+    vm.load(&code).unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.regs.pc, 4);
+    assert_eq!(vm.stack(), [1]); // the address of the call
+    let regs = vm.run().unwrap();
+    assert_eq!(regs.ax, 8);
+    assert_eq!(vm.stack(), [8]);
+    assert!(vm.halted());
+    assert_eq!(vm.steps(), 6);
+} //call_and_ret